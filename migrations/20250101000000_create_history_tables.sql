@@ -0,0 +1,23 @@
+-- Schema previously created ad hoc by PostgreSQL::ensure_table_exists. IF NOT EXISTS keeps
+-- databases that were set up before migrations existed working.
+CREATE TABLE IF NOT EXISTS depth_history (
+    start_time DOUBLE PRECISION NOT NULL,
+    end_time DOUBLE PRECISION NOT NULL,
+    asset_depth DOUBLE PRECISION NOT NULL,
+    rune_depth DOUBLE PRECISION NOT NULL,
+    asset_price DOUBLE PRECISION NOT NULL,
+    asset_price_usd DOUBLE PRECISION NOT NULL,
+    liquidity_units DOUBLE PRECISION NOT NULL,
+    members_count DOUBLE PRECISION NOT NULL,
+    synth_units DOUBLE PRECISION NOT NULL,
+    synth_supply DOUBLE PRECISION NOT NULL,
+    units DOUBLE PRECISION NOT NULL,
+    luvi DOUBLE PRECISION NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS rune_pool_history (
+    start_time DOUBLE PRECISION NOT NULL,
+    end_time DOUBLE PRECISION NOT NULL,
+    count DOUBLE PRECISION NOT NULL,
+    units DOUBLE PRECISION NOT NULL
+);
@@ -0,0 +1,27 @@
+-- start_time is the natural key of both tables. Rows duplicated by earlier append-only runs are
+-- removed first, keeping one row per start_time.
+DELETE FROM depth_history a
+    USING depth_history b
+    WHERE a.start_time = b.start_time AND a.ctid < b.ctid;
+
+DELETE FROM rune_pool_history a
+    USING rune_pool_history b
+    WHERE a.start_time = b.start_time AND a.ctid < b.ctid;
+
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'depth_history'::regclass AND contype = 'p'
+    ) THEN
+        ALTER TABLE depth_history ADD PRIMARY KEY (start_time);
+    END IF;
+
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conrelid = 'rune_pool_history'::regclass AND contype = 'p'
+    ) THEN
+        ALTER TABLE rune_pool_history ADD PRIMARY KEY (start_time);
+    END IF;
+END
+$$;
@@ -0,0 +1,18 @@
+-- Midgard integers (times, depths, units, supplies, counts) are stored losslessly as BIGINT,
+-- prices and luvi stay DOUBLE PRECISION.
+ALTER TABLE depth_history
+    ALTER COLUMN start_time TYPE BIGINT USING start_time::BIGINT,
+    ALTER COLUMN end_time TYPE BIGINT USING end_time::BIGINT,
+    ALTER COLUMN asset_depth TYPE BIGINT USING asset_depth::BIGINT,
+    ALTER COLUMN rune_depth TYPE BIGINT USING rune_depth::BIGINT,
+    ALTER COLUMN liquidity_units TYPE BIGINT USING liquidity_units::BIGINT,
+    ALTER COLUMN members_count TYPE BIGINT USING members_count::BIGINT,
+    ALTER COLUMN synth_units TYPE BIGINT USING synth_units::BIGINT,
+    ALTER COLUMN synth_supply TYPE BIGINT USING synth_supply::BIGINT,
+    ALTER COLUMN units TYPE BIGINT USING units::BIGINT;
+
+ALTER TABLE rune_pool_history
+    ALTER COLUMN start_time TYPE BIGINT USING start_time::BIGINT,
+    ALTER COLUMN end_time TYPE BIGINT USING end_time::BIGINT,
+    ALTER COLUMN count TYPE BIGINT USING count::BIGINT,
+    ALTER COLUMN units TYPE BIGINT USING units::BIGINT;
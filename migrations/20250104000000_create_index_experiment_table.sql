@@ -0,0 +1,6 @@
+-- The index experiment works on its own copy of the depth history table, so it leaves the
+-- benchmark data alone. Indexes an earlier experiment left on depth_history are dropped first,
+-- so they are not copied.
+DROP INDEX IF EXISTS depth_history_end_time_idx, depth_history_asset_price_usd_idx;
+
+CREATE TABLE IF NOT EXISTS depth_history_index_experiment (LIKE depth_history INCLUDING ALL);
@@ -0,0 +1,281 @@
+use std::fs;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use reqwest::StatusCode;
+
+use crate::{
+    config::Config,
+    db::{init_databases, Backend},
+    handlers::{
+        fetch_and_insert_data::ingest, read_data::read, reset::reset_backends, Outcome, Report,
+    },
+    metrics::resource_metrics::ResourceSampler,
+    runs::{run_benchmark, selected, RunRequest, Workload, DEFAULT_WORKLOADS},
+    utils::{
+        key_sampler::KeyDistribution,
+        midgard_api::Dataset,
+        write_metrics_into_file::{print_metrics, MetricsSink, METRICS_FILE},
+    },
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Benchmarks MongoDB, Postgres, SurrealDB, LevelDB and RocksDB with Midgard data",
+    long_about = None
+)]
+pub struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "Config file, instead of CONFIG_FILE or config.toml"
+    )]
+    pub config: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        help = "Backends to benchmark, comma separated (default: all)"
+    )]
+    pub backends: Vec<Backend>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "How the results are printed"
+    )]
+    pub format: OutputFormat,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Start the HTTP server (the default without a command)")]
+    Serve,
+    #[command(about = "Apply the pending Postgres migrations")]
+    Migrate,
+    #[command(about = "Fetch the datasets from Midgard and insert them into every backend")]
+    Ingest(IngestArgs),
+    #[command(about = "Read the stored datasets back from every backend")]
+    Read(ReadArgs),
+    #[command(about = "Ingest, then run the selected workloads")]
+    Run(RunArgs),
+    #[command(about = "Print the metrics recorded in performance-metrics.txt")]
+    Report(ReportArgs),
+    #[command(about = "Empty every backend")]
+    Reset,
+}
+
+impl Command {
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Serve => "serve",
+            Command::Migrate => "migrate",
+            Command::Ingest(_) => "ingest",
+            Command::Read(_) => "read",
+            Command::Run(_) => "run",
+            Command::Report(_) => "report",
+            Command::Reset => "reset",
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct IngestArgs {
+    #[arg(long, help = "Empty the backends before inserting")]
+    pub reset: bool,
+
+    #[arg(long, help = "Insert each dataset in a single transaction per backend")]
+    pub transactional: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        help = "Datasets to insert, comma separated (default: all)"
+    )]
+    pub datasets: Vec<Dataset>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReadArgs {
+    #[arg(
+        long,
+        help = "Reopen the embedded stores and read cold before reading warm"
+    )]
+    pub cold: bool,
+
+    #[arg(long, help = "With --cold, also read through fresh server connections")]
+    pub reconnect: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub ingest: IngestArgs,
+
+    #[command(flatten)]
+    pub read: ReadArgs,
+
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = DEFAULT_WORKLOADS,
+        help = "Workloads to run after the ingest, comma separated"
+    )]
+    pub workloads: Vec<Workload>,
+
+    #[arg(
+        long,
+        help = "Number of point lookups (default: workloads.point_lookup_count)"
+    )]
+    pub count: Option<usize>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Key distribution of the point lookups (default: workloads.key_distribution)"
+    )]
+    pub distribution: Option<KeyDistribution>,
+
+    #[arg(
+        long,
+        help = "Update price factor (default: workloads.update_price_factor)"
+    )]
+    pub factor: Option<f64>,
+}
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[arg(long, help = "Only print the last N metrics lines")]
+    pub last: Option<usize>,
+}
+
+impl RunArgs {
+    fn request(&self, backends: &[Backend]) -> RunRequest {
+        RunRequest {
+            backends: backends.to_vec(),
+            datasets: self.ingest.datasets.clone(),
+            reset: self.ingest.reset,
+            transactional: self.ingest.transactional,
+            cold: self.read.cold,
+            reconnect: self.read.reconnect,
+            workloads: self.workloads.clone(),
+            count: self.count,
+            distribution: self.distribution,
+            factor: self.factor,
+        }
+    }
+}
+
+fn report(args: &ReportArgs) -> Outcome {
+    let contents = fs::read_to_string(METRICS_FILE).map_err(|err| {
+        eprintln!("Failed to read {}: {}", METRICS_FILE, err);
+        (StatusCode::NOT_FOUND, "No metrics recorded yet")
+    })?;
+
+    let lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+    let skip = args.last.map_or(0, |last| lines.len().saturating_sub(last));
+
+    // printed like any other metrics, so `--format json` collects them too
+    for line in &lines[skip..] {
+        print_metrics(line);
+    }
+
+    Ok(Report::new("Reported metrics"))
+}
+
+async fn run_command(command: &Command, config: &Config, backends: &[Backend]) -> Outcome {
+    let database = init_databases(config).await.map_err(|err| {
+        eprintln!("{:?}", err);
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Failed to connect to the databases",
+        )
+    })?;
+
+    match command {
+        Command::Ingest(args) => {
+            let datasets = selected(&args.datasets, &Dataset::ALL);
+            ingest(
+                &database,
+                config,
+                backends,
+                &datasets,
+                args.reset,
+                args.transactional,
+            )
+            .await
+        }
+        Command::Read(args) => read(&database, config, backends, args.cold, args.reconnect).await,
+        Command::Run(args) => run_benchmark(&database, config, &args.request(backends)).await,
+        Command::Reset => reset_backends(&database, backends).await,
+        Command::Serve | Command::Migrate | Command::Report(_) => {
+            Err((StatusCode::BAD_REQUEST, "Not a benchmark command"))
+        }
+    }
+}
+
+// Runs a benchmark command without the HTTP server, through the same workloads as the routes.
+// Returns whether it succeeded, so the process can exit non-zero for scripts and CI.
+pub async fn execute(cli: &Cli, command: &Command, config: &Config) -> bool {
+    let backends = selected(&cli.backends, &Backend::ALL);
+
+    // with JSON output the metrics go into the document instead of stdout
+    let sink = match cli.format {
+        OutputFormat::Text => MetricsSink::default(),
+        OutputFormat::Json => MetricsSink::quiet(),
+    };
+
+    let outcome = sink
+        .clone()
+        .collect(async {
+            match command {
+                Command::Report(args) => report(args),
+                _ => {
+                    let sampler = ResourceSampler::start(&config.monitoring);
+                    let outcome = run_command(command, config, &backends).await;
+                    sampler
+                        .finish(&format!("Resources used during {}", command.name()))
+                        .await;
+                    outcome
+                }
+            }
+        })
+        .await;
+
+    // a backend that failed fails the command, the others were still measured
+    let (succeeded, message, results) = match outcome {
+        Ok(report) => (report.failures() == 0, report.summary(), report.results),
+        Err((_, message)) => (false, message.to_string(), Vec::new()),
+    };
+
+    match cli.format {
+        OutputFormat::Text if succeeded => println!("{}", message),
+        OutputFormat::Text => eprintln!("{}", message),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "command": command.name(),
+                "status": if succeeded { "ok" } else { "failed" },
+                "message": message,
+                "results": results,
+                "metrics": sink.lines(),
+            })
+        ),
+    }
+
+    succeeded
+}
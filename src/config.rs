@@ -0,0 +1,520 @@
+use std::{env, fmt::Display, fs, io::ErrorKind, net::ToSocketAddrs, str::FromStr};
+
+use dotenv::dotenv;
+use serde::{
+    de::{value::StrDeserializer, DeserializeOwned, IntoDeserializer},
+    Deserialize,
+};
+
+use crate::{
+    db::{
+        leveldb::LevelDBConfig,
+        mongodb::MongoDBConfig,
+        postgres::PostgresConfig,
+        rocksdb::RocksDBConfig,
+        surrealdb::{SurrealConfig, SurrealEngine},
+    },
+    metrics::resource_metrics::MonitoringConfig,
+    utils::{
+        durability::Durability,
+        key_sampler::KeyDistribution,
+        midgard_api::MidgardConfig,
+        types::{Error, Result},
+    },
+};
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "0.0.0.0:3000".to_string(),
+        }
+    }
+}
+
+// Midgard endpoints the benchmark data is fetched from.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatasetsConfig {
+    pub depth_history_url: String,
+    pub rune_pool_history_url: String,
+}
+
+impl Default for DatasetsConfig {
+    fn default() -> Self {
+        DatasetsConfig {
+            depth_history_url:
+                "https://midgard.ninerealms.com/v2/history/depths/BTC.BTC?interval=hour&count=400"
+                    .to_string(),
+            rune_pool_history_url:
+                "https://midgard.ninerealms.com/v2/history/runepool?interval=hour&count=400"
+                    .to_string(),
+        }
+    }
+}
+
+// Defaults of the workload routes, query parameters still override them per request.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkloadsConfig {
+    pub point_lookup_count: usize,
+    pub key_distribution: KeyDistribution,
+    pub update_price_factor: f64,
+    // a backend operation running longer is reported as timed out, 0 waits forever
+    pub operation_timeout_secs: u64,
+}
+
+impl Default for WorkloadsConfig {
+    fn default() -> Self {
+        WorkloadsConfig {
+            point_lookup_count: 1000,
+            key_distribution: KeyDistribution::Uniform,
+            update_price_factor: 1.01,
+            operation_timeout_secs: 300,
+        }
+    }
+}
+
+// Everything a benchmark setup consists of, read from one TOML file so setups can be versioned
+// and shared. Sections and keys left out keep their defaults.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub durability: Durability,
+    pub server: ServerConfig,
+    pub datasets: DatasetsConfig,
+    pub midgard: MidgardConfig,
+    pub workloads: WorkloadsConfig,
+    pub mongodb: MongoDBConfig,
+    pub postgres: PostgresConfig,
+    pub surrealdb: SurrealConfig,
+    pub rocksdb: RocksDBConfig,
+    pub leveldb: LevelDBConfig,
+    pub monitoring: MonitoringConfig,
+}
+
+impl Config {
+    // Reads the config file (`file`, else CONFIG_FILE, else `config.toml`), applies the env var
+    // overrides and validates the result. Every problem found is reported at once.
+    pub fn load(file: Option<String>) -> Result<Self> {
+        dotenv().ok();
+
+        let file = file.or_else(|| env_value("CONFIG_FILE"));
+        let path = file.as_deref().unwrap_or(DEFAULT_CONFIG_FILE);
+
+        let mut config = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| {
+                Error::InvalidConfiguration(format!("Failed to parse {}: {}", path, e))
+            })?,
+            // without a config file every setting comes from the env vars
+            Err(err) if err.kind() == ErrorKind::NotFound && file.is_none() => Config::default(),
+            Err(err) => {
+                return Err(Error::InvalidConfiguration(format!(
+                    "Failed to read {}: {}",
+                    path, err
+                )))
+            }
+        };
+
+        let mut errors = Vec::new();
+        config.apply_env_overrides(&mut errors);
+        config.validate(&mut errors);
+
+        if !errors.is_empty() {
+            return Err(Error::InvalidConfiguration(errors.join("\n")));
+        }
+
+        Ok(config)
+    }
+
+    // The durability a backend is opened with, its own `durability` key or the top level one.
+    pub fn durability_of(&self, backend: Option<Durability>) -> Durability {
+        backend.unwrap_or(self.durability)
+    }
+
+    // Env vars (or `.env`) override single settings, so URLs, credentials and PIDs can differ
+    // per machine while the file is shared.
+    fn apply_env_overrides(&mut self, errors: &mut Vec<String>) {
+        override_variant(&mut self.durability, "DURABILITY", errors);
+
+        override_value(&mut self.server.bind_address, "BIND_ADDRESS", errors);
+
+        let datasets = &mut self.datasets;
+        override_value(&mut datasets.depth_history_url, "DEPTH_HISTORY_URL", errors);
+        override_value(
+            &mut datasets.rune_pool_history_url,
+            "RUNE_POOL_HISTORY_URL",
+            errors,
+        );
+
+        let midgard = &mut self.midgard;
+        override_value(&mut midgard.timeout_secs, "MIDGARD_TIMEOUT_SECS", errors);
+        override_value(&mut midgard.max_retries, "MIDGARD_MAX_RETRIES", errors);
+        override_value(
+            &mut midgard.initial_backoff_ms,
+            "MIDGARD_INITIAL_BACKOFF_MS",
+            errors,
+        );
+        override_value(
+            &mut midgard.max_backoff_ms,
+            "MIDGARD_MAX_BACKOFF_MS",
+            errors,
+        );
+        override_value(
+            &mut midgard.requests_per_second,
+            "MIDGARD_REQUESTS_PER_SECOND",
+            errors,
+        );
+
+        let workloads = &mut self.workloads;
+        override_value(
+            &mut workloads.point_lookup_count,
+            "POINT_LOOKUP_COUNT",
+            errors,
+        );
+        override_variant(&mut workloads.key_distribution, "KEY_DISTRIBUTION", errors);
+        override_value(
+            &mut workloads.update_price_factor,
+            "UPDATE_PRICE_FACTOR",
+            errors,
+        );
+        override_value(
+            &mut workloads.operation_timeout_secs,
+            "OPERATION_TIMEOUT_SECS",
+            errors,
+        );
+
+        override_value(&mut self.mongodb.url, "MONGO_DATABASE_URL", errors);
+        override_option_variant(&mut self.mongodb.durability, "MONGO_DURABILITY", errors);
+
+        override_value(&mut self.postgres.url, "POSGRES_DATABASE_URL", errors);
+        override_value(
+            &mut self.postgres.max_connections,
+            "POSTGRES_MAX_CONNECTIONS",
+            errors,
+        );
+        override_option_variant(&mut self.postgres.durability, "POSTGRES_DURABILITY", errors);
+
+        let surrealdb = &mut self.surrealdb;
+        override_value(&mut surrealdb.url, "SURREAL_DATABASE_URL", errors);
+        override_value(&mut surrealdb.namespace, "SURREAL_NAMESPACE", errors);
+        override_value(&mut surrealdb.database, "SURREAL_DATABASE", errors);
+        override_variant(&mut surrealdb.auth_level, "SURREAL_AUTH_LEVEL", errors);
+        override_option(&mut surrealdb.username, "SURREAL_USERNAME", errors);
+        override_option(&mut surrealdb.password, "SURREAL_PASSWORD", errors);
+        if let Some(engines) = env_value("SURREAL_EMBEDDED_ENGINES") {
+            match engines
+                .split(',')
+                .filter(|engine| !engine.trim().is_empty())
+                .map(parse_variant)
+                .collect()
+            {
+                Ok(engines) => surrealdb.embedded_engines = engines,
+                Err(err) => errors.push(format!("SURREAL_EMBEDDED_ENGINES: {}", err)),
+            }
+        }
+        override_value(
+            &mut surrealdb.surrealkv_path,
+            "SURREAL_SURREALKV_PATH",
+            errors,
+        );
+        override_value(&mut surrealdb.rocksdb_path, "SURREAL_ROCKSDB_PATH", errors);
+
+        let rocksdb = &mut self.rocksdb;
+        override_value(&mut rocksdb.path, "ROCKSDB_PATH", errors);
+        override_option_variant(&mut rocksdb.durability, "ROCKSDB_DURABILITY", errors);
+        override_option(
+            &mut rocksdb.block_cache_size,
+            "ROCKSDB_BLOCK_CACHE_SIZE",
+            errors,
+        );
+        override_option(
+            &mut rocksdb.write_buffer_size,
+            "ROCKSDB_WRITE_BUFFER_SIZE",
+            errors,
+        );
+        override_option(&mut rocksdb.compression, "ROCKSDB_COMPRESSION", errors);
+        override_option(
+            &mut rocksdb.bloom_filter_bits_per_key,
+            "ROCKSDB_BLOOM_FILTER_BITS_PER_KEY",
+            errors,
+        );
+        override_option(
+            &mut rocksdb.max_open_files,
+            "ROCKSDB_MAX_OPEN_FILES",
+            errors,
+        );
+        override_option(
+            &mut rocksdb.compaction_style,
+            "ROCKSDB_COMPACTION_STYLE",
+            errors,
+        );
+
+        let leveldb = &mut self.leveldb;
+        override_value(&mut leveldb.path, "LEVELDB_PATH", errors);
+        override_option_variant(&mut leveldb.durability, "LEVELDB_DURABILITY", errors);
+        override_option(
+            &mut leveldb.block_cache_size,
+            "LEVELDB_BLOCK_CACHE_SIZE",
+            errors,
+        );
+        override_option(
+            &mut leveldb.write_buffer_size,
+            "LEVELDB_WRITE_BUFFER_SIZE",
+            errors,
+        );
+        override_option(&mut leveldb.compression, "LEVELDB_COMPRESSION", errors);
+        override_option(
+            &mut leveldb.bloom_filter_bits_per_key,
+            "LEVELDB_BLOOM_FILTER_BITS_PER_KEY",
+            errors,
+        );
+        override_option(
+            &mut leveldb.max_open_files,
+            "LEVELDB_MAX_OPEN_FILES",
+            errors,
+        );
+
+        let monitoring = &mut self.monitoring;
+        override_value(
+            &mut monitoring.sample_interval_ms,
+            "RESOURCE_SAMPLE_INTERVAL_MS",
+            errors,
+        );
+        override_option(&mut monitoring.mongodb_pid, "MONGO_PID", errors);
+        override_option(&mut monitoring.postgres_pid, "POSTGRES_PID", errors);
+        override_option(&mut monitoring.surrealdb_pid, "SURREAL_PID", errors);
+    }
+
+    fn validate(&self, errors: &mut Vec<String>) {
+        if let Err(err) = self.server.bind_address.to_socket_addrs() {
+            errors.push(format!(
+                "server.bind_address {:?} is invalid: {}",
+                self.server.bind_address, err
+            ));
+        }
+
+        for (key, url) in [
+            (
+                "datasets.depth_history_url",
+                &self.datasets.depth_history_url,
+            ),
+            (
+                "datasets.rune_pool_history_url",
+                &self.datasets.rune_pool_history_url,
+            ),
+        ] {
+            if let Err(err) = reqwest::Url::parse(url) {
+                errors.push(format!("{} {:?} is invalid: {}", key, url, err));
+            }
+        }
+
+        if self.workloads.point_lookup_count == 0 {
+            errors.push("workloads.point_lookup_count must be at least 1".to_string());
+        }
+        if !self.workloads.update_price_factor.is_finite()
+            || self.workloads.update_price_factor <= 0.0
+        {
+            errors.push("workloads.update_price_factor must be a positive number".to_string());
+        }
+
+        for (section, durability) in [
+            ("leveldb", self.durability_of(self.leveldb.durability)),
+            ("postgres", self.durability_of(self.postgres.durability)),
+        ] {
+            if durability == Durability::Off {
+                errors.push(format!(
+                    "durability off cannot be honoured by {0}, set {0}.durability to buffered, sync or default",
+                    section
+                ));
+            }
+        }
+
+        self.midgard.validate(errors);
+        self.mongodb.validate(errors);
+        self.postgres.validate(errors);
+        self.surrealdb.validate(errors);
+        self.rocksdb.validate(errors);
+        self.leveldb.validate(errors);
+        self.monitoring.validate(errors);
+
+        let mut directories = vec![
+            ("rocksdb.path", &self.rocksdb.path),
+            ("leveldb.path", &self.leveldb.path),
+        ];
+        for engine in &self.surrealdb.embedded_engines {
+            match engine {
+                SurrealEngine::Memory => {}
+                SurrealEngine::SurrealKv => {
+                    directories.push(("surrealdb.surrealkv_path", &self.surrealdb.surrealkv_path))
+                }
+                SurrealEngine::RocksDb => {
+                    directories.push(("surrealdb.rocksdb_path", &self.surrealdb.rocksdb_path))
+                }
+            }
+        }
+        for (i, (key, path)) in directories.iter().enumerate() {
+            for (other_key, other_path) in &directories[i + 1..] {
+                if path == other_path {
+                    errors.push(format!(
+                        "{} and {} must be different directories",
+                        key, other_key
+                    ));
+                }
+            }
+        }
+    }
+}
+
+// Formats an optional setting for the run log.
+pub fn describe<T: Display>(value: &Option<T>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => "default".to_string(),
+    }
+}
+
+// An unset or empty variable keeps the value from the file.
+fn env_value(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn override_value<T>(field: &mut T, name: &str, errors: &mut Vec<String>)
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env_value(name) {
+        match value.parse() {
+            Ok(v) => *field = v,
+            Err(err) => errors.push(format!("{}={:?} is invalid: {}", name, value, err)),
+        }
+    }
+}
+
+fn override_option<T>(field: &mut Option<T>, name: &str, errors: &mut Vec<String>)
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(value) = env_value(name) {
+        match value.parse() {
+            Ok(v) => *field = Some(v),
+            Err(err) => errors.push(format!("{}={:?} is invalid: {}", name, value, err)),
+        }
+    }
+}
+
+// Enum settings accept the same names in env vars as in the file.
+fn override_variant<T: DeserializeOwned>(field: &mut T, name: &str, errors: &mut Vec<String>) {
+    if let Some(value) = env_value(name) {
+        match parse_variant(&value) {
+            Ok(v) => *field = v,
+            Err(err) => errors.push(format!("{}: {}", name, err)),
+        }
+    }
+}
+
+fn override_option_variant<T: DeserializeOwned>(
+    field: &mut Option<T>,
+    name: &str,
+    errors: &mut Vec<String>,
+) {
+    if let Some(value) = env_value(name) {
+        match parse_variant(&value) {
+            Ok(v) => *field = Some(v),
+            Err(err) => errors.push(format!("{}: {}", name, err)),
+        }
+    }
+}
+
+fn parse_variant<T: DeserializeOwned>(
+    value: &str,
+) -> core::result::Result<T, serde::de::value::Error> {
+    let value = value.trim().to_lowercase();
+    let deserializer: StrDeserializer<serde::de::value::Error> = value.as_str().into_deserializer();
+    T::deserialize(deserializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_config() -> Config {
+        let mut config = Config::default();
+        config.mongodb.url = "mongodb://localhost:27017".to_string();
+        config.postgres.url = "postgres://postgres@localhost:5432/database_metrics".to_string();
+        config.surrealdb.url = "ws://localhost:8000".to_string();
+        config.surrealdb.namespace = "benchmark".to_string();
+        config.surrealdb.database = "database_metrics".to_string();
+        config
+    }
+
+    fn errors(config: &Config) -> Vec<String> {
+        let mut errors = Vec::new();
+        config.validate(&mut errors);
+        errors
+    }
+
+    #[test]
+    fn defaults_with_urls_are_valid() {
+        assert!(errors(&valid_config()).is_empty());
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let mut config = valid_config();
+        config.mongodb.url.clear();
+        config.workloads.point_lookup_count = 0;
+        config.workloads.update_price_factor = f64::NAN;
+
+        assert_eq!(errors(&config).len(), 3);
+    }
+
+    #[test]
+    fn durability_off_needs_overrides_for_leveldb_and_postgres() {
+        let mut config = valid_config();
+        config.durability = Durability::Off;
+        assert_eq!(errors(&config).len(), 2);
+
+        config.leveldb.durability = Some(Durability::Buffered);
+        config.postgres.durability = Some(Durability::Sync);
+        assert!(errors(&config).is_empty());
+        assert_eq!(
+            config.durability_of(config.rocksdb.durability),
+            Durability::Off
+        );
+    }
+
+    #[test]
+    fn data_directories_must_differ() {
+        let mut config = valid_config();
+        config.surrealdb.embedded_engines = vec![SurrealEngine::Memory, SurrealEngine::SurrealKv];
+        assert!(errors(&config).is_empty());
+
+        config.surrealdb.surrealkv_path = config.rocksdb.path.clone();
+        assert_eq!(
+            errors(&config),
+            vec!["rocksdb.path and surrealdb.surrealkv_path must be different directories"]
+        );
+    }
+
+    #[test]
+    fn variants_parse_case_insensitively() {
+        assert_eq!(
+            parse_variant::<Durability>(" Sync ").unwrap(),
+            Durability::Sync
+        );
+        assert!(parse_variant::<Durability>("fsync").is_err());
+    }
+}
@@ -1,4 +1,17 @@
-use crate::utils::types::{Error, Result};
+use std::{future::Future, time::Duration};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    utils::{
+        midgard_api::MidgardClient,
+        run_events::{emit, RunEvent},
+        types::{Error, Result},
+        write_metrics_into_file::record_metrics,
+    },
+};
 use leveldb::LevelDB;
 use mongodb::MongoDB;
 use postgres::PostgreSQL;
@@ -11,26 +24,193 @@ pub mod postgres;
 pub mod rocksdb;
 pub mod surrealdb;
 
+// Backends a benchmark run is limited to, SurrealDB covers the server and the embedded engines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[value(name = "mongodb")]
+    MongoDB,
+    #[value(name = "postgres")]
+    Postgres,
+    #[value(name = "surrealdb")]
+    SurrealDB,
+    #[value(name = "leveldb")]
+    LevelDB,
+    #[value(name = "rocksdb")]
+    RocksDB,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 5] = [
+        Backend::MongoDB,
+        Backend::Postgres,
+        Backend::SurrealDB,
+        Backend::LevelDB,
+        Backend::RocksDB,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::MongoDB => "MongoDB",
+            Backend::Postgres => "Postgres",
+            Backend::SurrealDB => "SurrealDB",
+            Backend::LevelDB => "LevelDB",
+            Backend::RocksDB => "RocksDB",
+        }
+    }
+}
+
 pub struct DataBases {
     pub mongodb: MongoDB,
     pub postgres: PostgreSQL,
     pub surrealdb: SurrealDBWrapper,
+    // embedded SurrealDB engines, benchmarked as additional backends
+    pub surrealdb_embedded: Vec<SurrealDBWrapper>,
     pub leveldb: LevelDB,
     pub rocksdb: RocksDB,
+    // `[workloads] operation_timeout_secs`, None waits forever
+    pub operation_timeout: Option<Duration>,
+    // shared by every fetch of the benchmark data, so the rate limit holds across runs
+    pub midgard: MidgardClient,
 }
 
-pub async fn init_databases() -> Result<DataBases> {
-    let mongodb = MongoDB::init().await.map_err(|e| e)?;
-    let postgres = PostgreSQL::init().await.map_err(|e| e)?;
-    let surrealdb = SurrealDBWrapper::init().await.map_err(|e| e)?;
-    let leveldb = LevelDB::init().await.map_err(|e| e)?;
-    let rocksdb = RocksDB::init().await.map_err(|e| e)?;
+pub async fn init_databases(config: &Config) -> Result<DataBases> {
+    let mongodb = MongoDB::init(
+        &config.mongodb,
+        config.durability_of(config.mongodb.durability),
+    )
+    .await
+    .map_err(|e| e)?;
+    let postgres = PostgreSQL::init(
+        &config.postgres,
+        config.durability_of(config.postgres.durability),
+    )
+    .await
+    .map_err(|e| e)?;
+    let surrealdb = SurrealDBWrapper::init(&config.surrealdb)
+        .await
+        .map_err(|e| e)?;
+    let surrealdb_embedded = SurrealDBWrapper::init_embedded_engines(&config.surrealdb).await?;
+    let leveldb = LevelDB::init(
+        &config.leveldb,
+        config.durability_of(config.leveldb.durability),
+    )
+    .await
+    .map_err(|e| e)?;
+    let rocksdb = RocksDB::init(
+        &config.rocksdb,
+        config.durability_of(config.rocksdb.durability),
+    )
+    .await
+    .map_err(|e| e)?;
+    let midgard = MidgardClient::new(&config.midgard)?;
 
     Ok(DataBases {
         mongodb,
         postgres,
         surrealdb,
+        surrealdb_embedded,
         leveldb,
         rocksdb,
+        operation_timeout: match config.workloads.operation_timeout_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
+        midgard,
     })
 }
+
+// Runs one backend operation, giving up after `timeout`. A hung backend is recorded as timed
+// out and the caller gets an error, so the run can go on with the other backends.
+pub async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    backend: &str,
+    operation: &str,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => {
+            let message = format!(
+                "{} timed out after {}s during {}",
+                backend,
+                timeout.as_secs(),
+                operation
+            );
+            record_metrics(format!("{}\n", message));
+            emit(RunEvent::BackendTimedOut {
+                backend: backend.to_string(),
+                operation: operation.to_string(),
+                timeout_secs: timeout.as_secs(),
+            });
+
+            Err(Error::OperationTimedOut(message))
+        }
+    }
+}
+
+// Every selected backend is reset even when an earlier one failed, the result is per backend.
+pub async fn reset_databases(
+    databases: &DataBases,
+    backends: &[Backend],
+) -> Vec<(String, Result<()>)> {
+    let timeout = databases.operation_timeout;
+    let mut results = Vec::new();
+
+    if backends.contains(&Backend::MongoDB) {
+        results.push((
+            "MongoDB".to_string(),
+            with_timeout(timeout, "MongoDB", "reset", databases.mongodb.reset()).await,
+        ));
+    }
+    if backends.contains(&Backend::Postgres) {
+        results.push((
+            "Postgres".to_string(),
+            with_timeout(timeout, "Postgres", "reset", databases.postgres.reset()).await,
+        ));
+    }
+    if backends.contains(&Backend::SurrealDB) {
+        results.push((
+            "SurrealDB".to_string(),
+            with_timeout(timeout, "SurrealDB", "reset", databases.surrealdb.reset()).await,
+        ));
+        for surrealdb in &databases.surrealdb_embedded {
+            results.push((
+                surrealdb.name.clone(),
+                with_timeout(timeout, &surrealdb.name, "reset", surrealdb.reset()).await,
+            ));
+        }
+    }
+    if backends.contains(&Backend::LevelDB) {
+        results.push((
+            "LevelDB".to_string(),
+            with_timeout(timeout, "LevelDB", "reset", databases.leveldb.reset()).await,
+        ));
+    }
+    if backends.contains(&Backend::RocksDB) {
+        results.push((
+            "RocksDB".to_string(),
+            with_timeout(timeout, "RocksDB", "reset", databases.rocksdb.reset()).await,
+        ));
+    }
+
+    results
+}
+
+// Settings the storage engines were opened with, logged at the start of every ingest run so
+// the timings can be traced back to the configuration that produced them.
+pub fn describe_storage_configuration(databases: &DataBases) -> String {
+    format!(
+        "Storage configuration:\n  RocksDB: durability={}, {}\n  LevelDB: durability={}, {}\n  MongoDB: durability={}\n  Postgres: durability={}\n",
+        databases.rocksdb.durability.as_str(),
+        databases.rocksdb.config.describe(),
+        databases.leveldb.durability.as_str(),
+        databases.leveldb.config.describe(),
+        databases.mongodb.durability.as_str(),
+        databases.postgres.durability.as_str(),
+    )
+}
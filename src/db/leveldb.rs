@@ -1,98 +1,494 @@
-use rusty_leveldb::{AsyncDB, Options};
-use std::sync::Arc;
+use rusty_leveldb::{
+    compressor::{CompressorId, NoneCompressor, SnappyCompressor},
+    AsyncDB, BloomPolicy, Options, Status, StatusCode, WriteBatch,
+};
+use serde::Deserialize;
+use std::{
+    rc::Rc,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use crate::{
+    config::describe,
     models::depth_history_model::DepthHistory,
-    utils::types::{Error, Result},
+    utils::{
+        disk_usage::directory_size,
+        durability::Durability,
+        kv_keys::{depth_history_start_time, is_legacy_key, is_legacy_record_key, migrated_key},
+        types::{Error, Result},
+    },
 };
 
+// The `[leveldb]` section of the config file: where the database lives and its storage engine
+// options. Unset options keep the rusty-leveldb defaults. LevelDB only has levelled compaction,
+// so there is no compaction style.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelDBConfig {
+    pub path: String,
+    pub block_cache_size: Option<usize>,
+    pub write_buffer_size: Option<usize>,
+    pub compression: Option<String>,
+    pub bloom_filter_bits_per_key: Option<u32>,
+    pub max_open_files: Option<usize>,
+    // overrides the top level durability
+    pub durability: Option<Durability>,
+}
+
+impl Default for LevelDBConfig {
+    fn default() -> Self {
+        LevelDBConfig {
+            path: "data/leveldb".to_string(),
+            block_cache_size: None,
+            write_buffer_size: None,
+            compression: None,
+            bloom_filter_bits_per_key: None,
+            max_open_files: None,
+            durability: None,
+        }
+    }
+}
+
+impl LevelDBConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.path.is_empty() {
+            errors.push("leveldb.path is not set".to_string());
+        }
+        if self.block_cache_size == Some(0) || self.write_buffer_size == Some(0) {
+            errors.push("leveldb cache and buffer sizes must be positive".to_string());
+        }
+        if let Err(err) = self.compressor() {
+            errors.push(format!("leveldb.compression: {:?}", err));
+        }
+    }
+
+    fn compressor(&self) -> Result<Option<u8>> {
+        let compression = match &self.compression {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        match compression.to_lowercase().as_str() {
+            "none" => Ok(Some(NoneCompressor::ID)),
+            "snappy" => Ok(Some(SnappyCompressor::ID)),
+            other => Err(Error::OperationFailed(format!(
+                "Unknown LevelDB compression {:?}, expected none or snappy.",
+                other
+            ))),
+        }
+    }
+
+    fn options(&self) -> Result<Options> {
+        let mut options = Options::default();
+
+        if let Some(size) = self.block_cache_size {
+            options.block_cache_capacity_bytes = size;
+        }
+        if let Some(size) = self.write_buffer_size {
+            options.write_buffer_size = size;
+        }
+        if let Some(files) = self.max_open_files {
+            options.max_open_files = files;
+        }
+        if let Some(bits) = self.bloom_filter_bits_per_key {
+            options.filter_policy = Rc::new(Box::new(BloomPolicy::new(bits)));
+        }
+        // the database has to be reopened with the compression it was written with
+        if let Some(compressor) = self.compressor()? {
+            options.compressor = compressor;
+        }
+
+        Ok(options)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "path={}, block_cache_size={}, write_buffer_size={}, compression={}, bloom_filter_bits_per_key={}, max_open_files={}",
+            self.path,
+            describe(&self.block_cache_size),
+            describe(&self.write_buffer_size),
+            describe(&self.compression),
+            describe(&self.bloom_filter_bits_per_key),
+            describe(&self.max_open_files),
+        )
+    }
+}
+
+// The database thread releases its lock file just after answering a close request.
+const REOPEN_ATTEMPTS: u32 = 20;
+const REOPEN_DELAY: Duration = Duration::from_millis(50);
+
 pub struct LevelDB {
-    db: Arc<AsyncDB>,
+    // None while the database is being reopened, or after a reopen failed or was cancelled
+    db: RwLock<Option<Arc<AsyncDB>>>,
+    pub durability: Durability,
+    pub config: LevelDBConfig,
 }
 
 impl LevelDB {
-    pub async fn init() -> Result<Self> {
-        let opt = Options::default();
-        let db = AsyncDB::new("data/leveldb", opt).map_err(|e| {
+    pub async fn init(config: &LevelDBConfig, durability: Durability) -> Result<Self> {
+        // rejected by the config validation, the log cannot be turned off
+        if durability == Durability::Off {
+            return Err(Error::InvalidConfiguration(
+                "LevelDB cannot run with durability off".to_string(),
+            ));
+        }
+
+        let db = Self::open(config)?;
+
+        let leveldb = LevelDB {
+            db: RwLock::new(Some(Arc::new(db))),
+            durability,
+            config: config.clone(),
+        };
+
+        leveldb.migrate_legacy_keys().await?;
+
+        Ok(leveldb)
+    }
+
+    fn open(config: &LevelDBConfig) -> Result<AsyncDB> {
+        AsyncDB::new(&config.path, config.options()?).map_err(|e| {
             Error::DataBaseConnectionFailed(format!("Failed to initialize LevelDB: {:?}", e))
-        })?;
+        })
+    }
+
+    // Opens the database again when a reopen left it closed, so a failed or cancelled reopen
+    // does not leave LevelDB unusable until the process restarts.
+    fn db(&self) -> Result<Arc<AsyncDB>> {
+        let mut db = self
+            .db
+            .write()
+            .map_err(|_| Error::OperationFailed("LevelDB handle lock is poisoned".to_string()))?;
 
-        Ok(LevelDB { db: Arc::new(db) })
+        match &*db {
+            Some(current) => Ok(current.clone()),
+            None => {
+                let opened = Arc::new(Self::open(&self.config)?);
+                *db = Some(opened.clone());
+                Ok(opened)
+            }
+        }
     }
 
-    pub async fn insert_data(&self, key: Vec<u8>, val: Vec<u8>) -> Result<()> {
-        self.db.put(key.clone(), val).await.map_err(|e| {
-            Error::DataBaseInsertionFailed(format!("Failed to insert data into LevelDB: {:?}", e))
-        })?;
+    // Closes and reopens the database, dropping its block cache and memtable so the next reads
+    // come from disk (or the OS page cache). Fails if another request still holds the database.
+    // The new handle is only stored once it opened, if closing or opening fails (or this future
+    // is dropped) the next use of the database opens it again.
+    pub async fn reopen(&self) -> Result<()> {
+        let current = self
+            .db
+            .write()
+            .map_err(|_| Error::OperationFailed("LevelDB handle lock is poisoned".to_string()))?
+            .take();
 
-        let mut keys_index: Vec<Vec<u8>> = match self.db.get(b"_keys_index".to_vec()).await {
-            Ok(Some(index)) => serde_json::from_slice(&index).map_err(|e| {
-                Error::DataBaseReadFailed(format!(
-                    "Failed to deserialize keys index from LevelDB: {:?}",
-                    e
-                ))
-            })?,
-            Ok(None) => vec![],
-            Err(e) => {
-                return Err(Error::DataBaseReadFailed(format!(
-                    "Failed to retrieve keys index from LevelDB: {:?}",
-                    e
-                )))
+        if let Some(current) = current {
+            let current = match Arc::try_unwrap(current) {
+                Ok(current) => current,
+                Err(current) => {
+                    self.set_db(current)?;
+                    return Err(Error::OperationFailed(
+                        "LevelDB is still in use and cannot be reopened".to_string(),
+                    ));
+                }
+            };
+
+            current
+                .close()
+                .await
+                .map_err(|e| Error::OperationFailed(format!("Failed to close LevelDB: {:?}", e)))?;
+        }
+
+        let mut attempt = 1;
+        let db = loop {
+            match Self::open(&self.config) {
+                Ok(db) => break db,
+                Err(_) if attempt < REOPEN_ATTEMPTS => {
+                    attempt += 1;
+                    tokio::time::sleep(REOPEN_DELAY).await;
+                }
+                Err(err) => return Err(err),
             }
         };
 
-        keys_index.push(key.clone());
-        self.db
-            .put(
-                b"_keys_index".to_vec(),
-                serde_json::to_vec(&keys_index).map_err(|e| {
-                    Error::DataBaseInsertionFailed(format!(
-                        "Failed to serialize updated keys index for LevelDB: {:?}",
-                        e
-                    ))
-                })?,
-            )
-            .await
-            .map_err(|e| {
-                Error::DataBaseInsertionFailed(format!(
-                    "Failed to update keys index in LevelDB: {:?}",
-                    e
-                ))
-            })?;
+        self.set_db(Arc::new(db))
+    }
+
+    fn set_db(&self, db: Arc<AsyncDB>) -> Result<()> {
+        *self
+            .db
+            .write()
+            .map_err(|_| Error::OperationFailed("LevelDB handle lock is poisoned".to_string()))? =
+            Some(db);
 
         Ok(())
     }
 
-    pub async fn read_data(&self) -> Result<()> {
-        let keys_index: Vec<Vec<u8>> = match self.db.get(b"_keys_index".to_vec()).await {
+    // LevelDB always writes its log, `sync` only decides whether each write is fsync'd.
+    fn sync(&self) -> bool {
+        self.durability == Durability::Sync
+    }
+
+    async fn put(&self, key: Vec<u8>, val: Vec<u8>) -> rusty_leveldb::Result<()> {
+        let db = self.db().map_err(|e| Status {
+            code: StatusCode::AsyncError,
+            err: format!("{:?}", e),
+        })?;
+
+        if !self.sync() {
+            return db.put(key, val).await;
+        }
+
+        let mut batch = WriteBatch::default();
+        batch.put(&key, &val);
+        db.write(batch, true).await
+    }
+
+    async fn read_keys_index(&self) -> Result<Vec<Vec<u8>>> {
+        let db = self.db()?;
+        match db.get(b"_keys_index".to_vec()).await {
             Ok(Some(index)) => serde_json::from_slice(&index).map_err(|e| {
                 Error::DataBaseReadFailed(format!(
                     "Failed to deserialize keys index from LevelDB: {:?}",
                     e
                 ))
+            }),
+            Ok(None) => Ok(vec![]),
+            Err(e) => Err(Error::DataBaseReadFailed(format!(
+                "Failed to retrieve keys index from LevelDB: {:?}",
+                e
+            ))),
+        }
+    }
+
+    async fn write_keys_index(&self, keys_index: &[Vec<u8>]) -> Result<()> {
+        self.put(
+            b"_keys_index".to_vec(),
+            serde_json::to_vec(keys_index).map_err(|e| {
+                Error::DataBaseInsertionFailed(format!(
+                    "Failed to serialize updated keys index for LevelDB: {:?}",
+                    e
+                ))
             })?,
-            Ok(None) => {
-                println!("No data found in LevelDB!");
-                return Ok(());
+        )
+        .await
+        .map_err(|e| {
+            Error::DataBaseInsertionFailed(format!(
+                "Failed to update keys index in LevelDB: {:?}",
+                e
+            ))
+        })
+    }
+
+    // Moves records stored under the old unprefixed keys to their dataset prefix and drops the
+    // old index experiment entries, in one batch with the updated keys index. A record already
+    // stored under its prefixed key is newer and is kept.
+    async fn migrate_legacy_keys(&self) -> Result<()> {
+        let db = self.db()?;
+        let (legacy, mut keys_index): (Vec<Vec<u8>>, Vec<Vec<u8>>) = self
+            .read_keys_index()
+            .await?
+            .into_iter()
+            .partition(|key| is_legacy_key(key));
+
+        if legacy.is_empty() {
+            return Ok(());
+        }
+
+        let mut batch = WriteBatch::default();
+        for key in legacy {
+            if is_legacy_record_key(&key) {
+                let migrated = self
+                    .get_data(key.clone())
+                    .await?
+                    .and_then(|val| migrated_key(&val).map(|new_key| (new_key, val)));
+
+                if let Some((new_key, val)) = migrated {
+                    if !keys_index.contains(&new_key) {
+                        batch.put(&new_key, &val);
+                        keys_index.push(new_key);
+                    }
+                }
             }
-            Err(e) => {
-                return Err(Error::DataBaseReadFailed(format!(
-                    "Failed to retrieve keys index from LevelDB: {:?}",
-                    e
-                )))
+
+            batch.delete(&key);
+        }
+
+        let index = serde_json::to_vec(&keys_index).map_err(|e| {
+            Error::DataBaseInsertionFailed(format!(
+                "Failed to serialize updated keys index for LevelDB: {:?}",
+                e
+            ))
+        })?;
+        batch.put(b"_keys_index", &index);
+
+        db.write(batch, true).await.map_err(|e| {
+            Error::OperationFailed(format!("Failed to migrate legacy keys in LevelDB: {:?}", e))
+        })
+    }
+
+    pub async fn insert_data(&self, key: Vec<u8>, val: Vec<u8>) -> Result<()> {
+        self.put(key.clone(), val).await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to insert data into LevelDB: {:?}", e))
+        })?;
+
+        let mut keys_index = self.read_keys_index().await?;
+
+        // re-inserting an existing key overwrites its value, the index must not list it twice
+        if keys_index.contains(&key) {
+            return Ok(());
+        }
+
+        keys_index.push(key);
+        self.write_keys_index(&keys_index).await
+    }
+
+    // LevelDB has no transactions; a WriteBatch is applied atomically, so the records and the
+    // updated keys index land together or not at all.
+    pub async fn insert_data_in_transaction(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let db = self.db()?;
+        let mut keys_index = self.read_keys_index().await?;
+
+        let mut batch = WriteBatch::default();
+        for (key, val) in entries {
+            batch.put(&key, &val);
+            if !keys_index.contains(&key) {
+                keys_index.push(key);
             }
-        };
+        }
 
-        for key in keys_index {
-            self.db.get(key.clone()).await.map_err(|e| {
+        let index = serde_json::to_vec(&keys_index).map_err(|e| {
+            Error::DataBaseInsertionFailed(format!(
+                "Failed to serialize updated keys index for LevelDB: {:?}",
+                e
+            ))
+        })?;
+        batch.put(b"_keys_index", &index);
+
+        db.write(batch, self.sync()).await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to write batch to LevelDB: {:?}", e))
+        })
+    }
+
+    // Overwrites the value of a key that is already tracked by the keys index.
+    pub async fn update_data(&self, key: Vec<u8>, val: Vec<u8>) -> Result<()> {
+        self.put(key, val).await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to update data in LevelDB: {:?}", e))
+        })
+    }
+
+    pub async fn get_data(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let db = self.db()?;
+        db.get(key.clone()).await.map_err(|e| {
+            Error::DataBaseReadFailed(format!(
+                "Failed to retrieve value for key {:?} from LevelDB: {:?}",
+                String::from_utf8_lossy(&key),
+                e
+            ))
+        })
+    }
+
+    pub async fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let keys_index = self.read_keys_index().await?;
+
+        let mut values = Vec::new();
+        for key in keys_index.into_iter().filter(|key| key.starts_with(prefix)) {
+            if let Some(val) = self.get_data(key).await? {
+                values.push(val);
+            }
+        }
+
+        Ok(values)
+    }
+
+    pub async fn read_depth_history_start_times(&self) -> Result<Vec<i64>> {
+        Ok(self
+            .read_keys_index()
+            .await?
+            .iter()
+            .filter_map(|key| depth_history_start_time(key))
+            .collect())
+    }
+
+    // AsyncDB has no iterators, so range scans filter the keys index and fetch the matching keys.
+    pub async fn scan_range(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let keys_index = self.read_keys_index().await?;
+
+        let mut values = Vec::new();
+        for key in keys_index
+            .into_iter()
+            .filter(|key| key.as_slice() >= from && key.as_slice() < to)
+        {
+            if let Some(val) = self.get_data(key).await? {
+                values.push(val);
+            }
+        }
+
+        Ok(values)
+    }
+
+    pub async fn delete_prefix(&self, prefix: &[u8]) -> Result<()> {
+        let db = self.db()?;
+        let (deleted, kept): (Vec<Vec<u8>>, Vec<Vec<u8>>) = self
+            .read_keys_index()
+            .await?
+            .into_iter()
+            .partition(|key| key.starts_with(prefix));
+
+        if deleted.is_empty() {
+            return Ok(());
+        }
+
+        for key in deleted {
+            db.delete(key).await.map_err(|e| {
+                Error::OperationFailed(format!("Failed to delete key in LevelDB: {:?}", e))
+            })?;
+        }
+
+        self.write_keys_index(&kept).await
+    }
+
+    // Reads every key of the keys index, returns the number of entries read.
+    pub async fn read_data(&self) -> Result<usize> {
+        let db = self.db()?;
+        let keys_index = self.read_keys_index().await?;
+
+        for key in &keys_index {
+            db.get(key.clone()).await.map_err(|e| {
                 Error::DataBaseReadFailed(format!(
                     "Failed to retrieve value for key {:?} from LevelDB: {:?}",
-                    String::from_utf8_lossy(&key),
+                    String::from_utf8_lossy(key),
                     e
                 ))
             })?;
         }
 
+        Ok(keys_index.len())
+    }
+
+    // Bytes on disk, including the log for writes that are not compacted into tables yet.
+    pub async fn storage_size(&self) -> Result<u64> {
+        directory_size(&self.config.path)
+    }
+
+    pub async fn reset(&self) -> Result<()> {
+        let db = self.db()?;
+        let keys_index = self.read_keys_index().await?;
+        if keys_index.is_empty() {
+            return Ok(());
+        }
+
+        for key in keys_index {
+            db.delete(key).await.map_err(|e| {
+                Error::OperationFailed(format!("Failed to delete key in LevelDB: {:?}", e))
+            })?;
+        }
+
+        db.delete(b"_keys_index".to_vec()).await.map_err(|e| {
+            Error::OperationFailed(format!("Failed to delete keys index in LevelDB: {:?}", e))
+        })?;
+
         Ok(())
     }
 }
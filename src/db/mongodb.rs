@@ -1,31 +1,128 @@
-use dotenv::dotenv;
-use std::env;
-
-use mongodb::{bson::doc, results::InsertOneResult, Client, Collection};
+use mongodb::{
+    bson::{doc, Bson, Document},
+    options::{Acknowledgment, CollectionOptions, IndexOptions, TransactionOptions, WriteConcern},
+    results::InsertOneResult,
+    Client, Collection, IndexModel,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    models::{depth_history_model::DepthHistory, rune_pool_history_model::RunePoolHistory},
-    utils::types::{Error, Result},
+    models::{
+        aggregation_model::{
+            DailyDepthAggregate, DepthHistoryAggregates, WeeklyUnitsAggregate, SECONDS_PER_DAY,
+            SECONDS_PER_WEEK,
+        },
+        depth_history_model::DepthHistory,
+        rune_pool_history_model::RunePoolHistory,
+    },
+    utils::{
+        durability::Durability,
+        types::{Error, Result},
+    },
 };
 
+// Keeps one document per startTime, the one inserted last.
+async fn remove_duplicates<T: Send + Sync>(collection: &Collection<T>, name: &str) -> Result<()> {
+    let mut cursor = collection
+        .aggregate(vec![
+            doc! { "$sort": { "_id": 1 } },
+            doc! { "$group": { "_id": "$startTime", "ids": { "$push": "$_id" } } },
+            doc! { "$match": { "ids.1": { "$exists": true } } },
+        ])
+        .await
+        .map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to find duplicated {}: {}", name, e))
+        })?;
+
+    let mut duplicates = Vec::new();
+    while cursor.advance().await.map_err(|e| {
+        Error::DataBaseReadFailed(format!("Failed to find duplicated {}: {}", name, e))
+    })? {
+        let group = cursor.deserialize_current().map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to decode duplicated {}: {}", name, e))
+        })?;
+        let ids = group.get_array("ids").map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to decode duplicated {}: {}", name, e))
+        })?;
+
+        // ObjectIds grow with insertion time, every id but the last is removed
+        if let Some((_, older)) = ids.split_last() {
+            duplicates.extend(older.iter().cloned());
+        }
+    }
+
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+
+    collection
+        .delete_many(doc! { "_id": { "$in": duplicates } })
+        .await
+        .map_err(|e| {
+            Error::OperationFailed(format!("Failed to remove duplicated {}: {}", name, e))
+        })?;
+
+    Ok(())
+}
+
+// Aggregation results come back as documents whose numbers may be doubles or integers.
+fn get_number(document: &Document, field: &str) -> Result<f64> {
+    match document.get(field) {
+        Some(Bson::Double(v)) => Ok(*v),
+        Some(Bson::Int32(v)) => Ok(*v as f64),
+        Some(Bson::Int64(v)) => Ok(*v as f64),
+        other => Err(Error::DataBaseReadFailed(format!(
+            "Unexpected value for {} in aggregation result: {:?}",
+            field, other
+        ))),
+    }
+}
+
+fn get_integer(document: &Document, field: &str) -> Result<i64> {
+    match document.get(field) {
+        Some(Bson::Int32(v)) => Ok(*v as i64),
+        Some(Bson::Int64(v)) => Ok(*v),
+        other => Err(Error::DataBaseReadFailed(format!(
+            "Unexpected value for {} in aggregation result: {:?}",
+            field, other
+        ))),
+    }
+}
+
+const DATABASE_NAME: &str = "database_metrics";
+
+// The `[mongodb]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MongoDBConfig {
+    pub url: String,
+    // overrides the top level durability
+    pub durability: Option<Durability>,
+}
+
+impl MongoDBConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.url.is_empty() {
+            errors.push("mongodb.url is not set".to_string());
+        }
+    }
+}
+
 pub struct MongoDB {
+    // kept for sessions, transactions need one and collections cannot start them
+    client: Client,
+    pub durability: Durability,
+    // None leaves the write concern to the server
+    write_concern: Option<WriteConcern>,
     pub depth_history: Collection<DepthHistory>,
     pub rune_pule_history: Collection<RunePoolHistory>,
+    // copy of the depth history used by the index experiment, so it leaves the benchmark data alone
+    pub index_experiment: Collection<DepthHistory>,
 }
 
 impl MongoDB {
-    pub async fn init() -> Result<Self> {
-        dotenv().ok();
-        let uri = match env::var("MONGO_DATABASE_URL") {
-            Ok(v) => v.to_string(),
-            Err(_) => {
-                return Err(Error::DataBaseConnectionFailed(
-                    "Unable to read mongodb uri.".to_string(),
-                ))
-            }
-        };
-
-        let client: Option<Client> = Some(Client::with_uri_str(uri).await.unwrap());
+    pub async fn init(config: &MongoDBConfig, durability: Durability) -> Result<Self> {
+        let client: Option<Client> = Some(Client::with_uri_str(&config.url).await.unwrap());
 
         let client = match client {
             Some(clt) => clt,
@@ -36,16 +133,128 @@ impl MongoDB {
             }
         };
 
-        let db = client.database("database_metrics");
+        let db = client.database(DATABASE_NAME);
+
+        let write_concern = match durability {
+            Durability::Default => None,
+            Durability::Off => Some(WriteConcern::builder().w(Acknowledgment::Nodes(0)).build()),
+            Durability::Buffered => Some(
+                WriteConcern::builder()
+                    .w(Acknowledgment::Nodes(1))
+                    .journal(false)
+                    .build(),
+            ),
+            Durability::Sync => Some(
+                WriteConcern::builder()
+                    .w(Acknowledgment::Nodes(1))
+                    .journal(true)
+                    .build(),
+            ),
+        };
+        let collection_options = CollectionOptions::builder()
+            .write_concern(write_concern.clone())
+            .build();
 
-        let depth_history_collection: Collection<DepthHistory> = db.collection("depth_history");
+        let depth_history_collection: Collection<DepthHistory> =
+            db.collection_with_options("depth_history", collection_options.clone());
 
-        let rune_pool_collection: Collection<RunePoolHistory> = db.collection("rune_pool_history");
+        let rune_pool_collection: Collection<RunePoolHistory> =
+            db.collection_with_options("rune_pool_history", collection_options.clone());
 
-        Ok(MongoDB {
+        let index_experiment_collection: Collection<DepthHistory> =
+            db.collection_with_options("depth_history_index_experiment", collection_options);
+
+        let mongodb = MongoDB {
+            client,
+            durability,
+            write_concern,
             depth_history: depth_history_collection,
             rune_pule_history: rune_pool_collection,
-        })
+            index_experiment: index_experiment_collection,
+        };
+
+        mongodb.ensure_indexes().await?;
+
+        Ok(mongodb)
+    }
+
+    // start_time is the natural key of both collections, the unique index makes upserts idempotent.
+    // Documents duplicated by earlier append-only runs are removed first, as the Postgres
+    // migration does, otherwise the index cannot be built on an existing database.
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        remove_duplicates(&self.depth_history, "depth history").await?;
+        remove_duplicates(&self.rune_pule_history, "rune pool history").await?;
+
+        let start_time_index = || {
+            IndexModel::builder()
+                .keys(doc! { "startTime": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build()
+        };
+
+        self.depth_history
+            .create_index(start_time_index())
+            .await
+            .map_err(|e| {
+                Error::DataBaseCreationFailed(format!(
+                    "Depth history index creation failed, check that every document has a startTime: {}",
+                    e
+                ))
+            })?;
+
+        self.rune_pule_history
+            .create_index(start_time_index())
+            .await
+            .map_err(|e| {
+                Error::DataBaseCreationFailed(format!(
+                    "Rune pool history index creation failed, check that every document has a startTime: {}",
+                    e
+                ))
+            })?;
+
+        Ok(())
+    }
+
+    // Secondary indexes of the index experiment collection.
+    pub async fn create_secondary_indexes(&self) -> Result<()> {
+        for (name, field) in [
+            ("depth_history_end_time_idx", "endTime"),
+            ("depth_history_asset_price_usd_idx", "assetPriceUSD"),
+        ] {
+            let index = IndexModel::builder()
+                .keys(doc! { field: 1 })
+                .options(IndexOptions::builder().name(name.to_string()).build())
+                .build();
+
+            self.index_experiment
+                .create_index(index)
+                .await
+                .map_err(|e| {
+                    Error::DataBaseCreationFailed(format!("Index {} creation failed: {}", name, e))
+                })?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn insert_index_experiment_record(&self, depth_history: &DepthHistory) -> Result<()> {
+        self.index_experiment
+            .insert_one(depth_history)
+            .await
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Index experiment insert failed: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    // Dropping the collection drops its secondary indexes as well.
+    pub async fn clear_index_experiment(&self) -> Result<()> {
+        self.index_experiment.drop().await.map_err(|e| {
+            Error::OperationFailed(format!("Failed to drop index experiment collection: {}", e))
+        })?;
+
+        Ok(())
     }
 
     pub async fn insert_depth_history(
@@ -56,7 +265,9 @@ impl MongoDB {
             .depth_history
             .insert_one(depth_history)
             .await
-            .expect("Faild to insert depth history");
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Depth history insert failed: {}", e))
+            })?;
 
         Ok(insert_result)
     }
@@ -69,36 +280,324 @@ impl MongoDB {
             .rune_pule_history
             .insert_one(rune_pool_history)
             .await
-            .expect("Failed to insert Rune pool history");
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Rune pool history insert failed: {}", e))
+            })?;
 
         Ok(insert_result)
     }
 
+    // Multi-document transactions need a replica set (a single-node one is enough).
+    async fn insert_in_transaction<T: Serialize + Send + Sync>(
+        &self,
+        collection: &Collection<T>,
+        records: &[T],
+    ) -> Result<()> {
+        if self.durability == Durability::Off {
+            return Err(Error::InvalidConfiguration(
+                "MongoDB transactions need acknowledged writes, durability off (w=0) cannot be used with the transactional mode".to_string(),
+            ));
+        }
+
+        let mut session = self.client.start_session().await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to start session: {}", e))
+        })?;
+
+        // inside a transaction only the commit takes a write concern
+        session
+            .start_transaction()
+            .with_options(
+                TransactionOptions::builder()
+                    .write_concern(self.write_concern.clone())
+                    .build(),
+            )
+            .await
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Failed to start transaction: {}", e))
+            })?;
+
+        for record in records {
+            if let Err(e) = collection.insert_one(record).session(&mut session).await {
+                let _ = session.abort_transaction().await;
+                return Err(Error::DataBaseInsertionFailed(format!(
+                    "Insert into {} failed: {}",
+                    collection.name(),
+                    e
+                )));
+            }
+        }
+
+        session.commit_transaction().await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to commit transaction: {}", e))
+        })
+    }
+
+    pub async fn insert_depth_history_in_transaction(
+        &self,
+        records: &[DepthHistory],
+    ) -> Result<()> {
+        self.insert_in_transaction(&self.depth_history, records)
+            .await
+    }
+
+    pub async fn insert_rune_pool_history_in_transaction(
+        &self,
+        records: &[RunePoolHistory],
+    ) -> Result<()> {
+        self.insert_in_transaction(&self.rune_pule_history, records)
+            .await
+    }
+
+    pub async fn scale_depth_history_price(&self, start_time: i64, factor: f64) -> Result<()> {
+        self.depth_history
+            .update_one(
+                doc! { "startTime": start_time },
+                doc! { "$mul": { "assetPriceUSD": factor } },
+            )
+            .await
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Depth history update failed: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn scale_depth_history_prices_from(
+        &self,
+        start_time: i64,
+        factor: f64,
+    ) -> Result<()> {
+        self.depth_history
+            .update_many(
+                doc! { "startTime": { "$gte": start_time } },
+                doc! { "$mul": { "assetPriceUSD": factor } },
+            )
+            .await
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Depth history bulk update failed: {}", e))
+            })?;
+
+        Ok(())
+    }
+
     pub async fn read_depth_history(&self) -> Result<Vec<DepthHistory>> {
-        let mut cursor = self
-            .depth_history
-            .find(doc! {})
+        self.find_depth_history(doc! {}).await
+    }
+
+    pub async fn read_depth_history_start_times(&self) -> Result<Vec<i64>> {
+        Ok(self
+            .read_depth_history()
+            .await?
+            .into_iter()
+            .map(|record| record.start_time)
+            .collect())
+    }
+
+    pub async fn upsert_depth_history(&self, depth_history: &DepthHistory) -> Result<()> {
+        self.depth_history
+            .replace_one(
+                doc! { "startTime": depth_history.start_time },
+                depth_history,
+            )
+            .upsert(true)
+            .await
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Depth history upsert failed: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn upsert_rune_pool_history(
+        &self,
+        rune_pool_history: &RunePoolHistory,
+    ) -> Result<()> {
+        self.rune_pule_history
+            .replace_one(
+                doc! { "startTime": rune_pool_history.start_time },
+                rune_pool_history,
+            )
+            .upsert(true)
             .await
-            .expect("Failed to retrieve depth history documents.");
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Rune pool history upsert failed: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn find_depth_history_by_start_time(
+        &self,
+        start_time: i64,
+    ) -> Result<Option<DepthHistory>> {
+        self.depth_history
+            .find_one(doc! { "startTime": start_time })
+            .await
+            .map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to look up depth history: {}", e))
+            })
+    }
+
+    async fn find_depth_history(&self, filter: Document) -> Result<Vec<DepthHistory>> {
+        Self::find_in(&self.depth_history, filter).await
+    }
+
+    async fn find_in(
+        collection: &Collection<DepthHistory>,
+        filter: Document,
+    ) -> Result<Vec<DepthHistory>> {
+        let mut cursor = collection.find(filter).await.map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to query depth history: {}", e))
+        })?;
+
+        let mut results = Vec::new();
+        while cursor.advance().await.map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to read depth history: {}", e))
+        })? {
+            results.push(cursor.deserialize_current().map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to decode depth history: {}", e))
+            })?);
+        }
+
+        Ok(results)
+    }
+
+    pub async fn find_index_experiment_by_end_time_range(
+        &self,
+        from_end_time: i64,
+        to_end_time: i64,
+    ) -> Result<Vec<DepthHistory>> {
+        Self::find_in(
+            &self.index_experiment,
+            doc! { "endTime": { "$gte": from_end_time, "$lte": to_end_time } },
+        )
+        .await
+    }
+
+    pub async fn find_index_experiment_by_min_price(
+        &self,
+        min_asset_price_usd: f64,
+    ) -> Result<Vec<DepthHistory>> {
+        Self::find_in(
+            &self.index_experiment,
+            doc! { "assetPriceUSD": { "$gte": min_asset_price_usd } },
+        )
+        .await
+    }
+
+    async fn run_aggregation(&self, pipeline: Vec<Document>) -> Result<Vec<Document>> {
+        let mut cursor = self.depth_history.aggregate(pipeline).await.map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to aggregate depth history: {}", e))
+        })?;
 
         let mut results = Vec::new();
-        while cursor.advance().await.unwrap() {
-            results.push(cursor.deserialize_current().unwrap());
+        while cursor.advance().await.map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to read aggregation result: {}", e))
+        })? {
+            results.push(cursor.deserialize_current().map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to decode aggregation result: {}", e))
+            })?);
         }
+
         Ok(results)
     }
 
+    pub async fn aggregate_depth_history(&self) -> Result<DepthHistoryAggregates> {
+        let daily = self
+            .run_aggregation(vec![
+                doc! { "$group": {
+                    "_id": { "$floor": { "$divide": ["$startTime", SECONDS_PER_DAY] } },
+                    "avg_asset_price_usd": { "$avg": "$assetPriceUSD" },
+                    "max_rune_depth": { "$max": "$runeDepth" },
+                } },
+                doc! { "$sort": { "_id": 1 } },
+            ])
+            .await?
+            .iter()
+            .map(|document| {
+                Ok(DailyDepthAggregate {
+                    day: get_number(document, "_id")? as i64,
+                    avg_asset_price_usd: get_number(document, "avg_asset_price_usd")?,
+                    max_rune_depth: get_integer(document, "max_rune_depth")?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let weekly = self
+            .run_aggregation(vec![
+                doc! { "$group": {
+                    "_id": { "$floor": { "$divide": ["$startTime", SECONDS_PER_WEEK] } },
+                    "total_units": { "$sum": "$units" },
+                } },
+                doc! { "$sort": { "_id": 1 } },
+            ])
+            .await?
+            .iter()
+            .map(|document| {
+                Ok(WeeklyUnitsAggregate {
+                    week: get_number(document, "_id")? as i64,
+                    total_units: get_integer(document, "total_units")?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(DepthHistoryAggregates { daily, weekly })
+    }
+
     pub async fn read_rune_pool_history(&self) -> Result<Vec<RunePoolHistory>> {
-        let mut cursor = self
-            .rune_pule_history
-            .find(doc! {})
-            .await
-            .expect("Failed to retrieve depth history documents.");
+        let mut cursor = self.rune_pule_history.find(doc! {}).await.map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to query rune pool history: {}", e))
+        })?;
 
         let mut results = Vec::new();
-        while cursor.advance().await.unwrap() {
-            results.push(cursor.deserialize_current().unwrap());
+        while cursor.advance().await.map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to read rune pool history: {}", e))
+        })? {
+            results.push(cursor.deserialize_current().map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to decode rune pool history: {}", e))
+            })?);
         }
+
         Ok(results)
     }
+
+    // Storage plus index bytes of both collections, as reported by collStats.
+    pub async fn storage_size(&self) -> Result<u64> {
+        let db = self.client.database(DATABASE_NAME);
+
+        let mut size = 0.0;
+        for collection in [self.depth_history.name(), self.rune_pule_history.name()] {
+            let stats = db
+                .run_command(doc! { "collStats": collection })
+                .await
+                .map_err(|e| {
+                    Error::DataBaseReadFailed(format!(
+                        "Failed to read collStats of {}: {}",
+                        collection, e
+                    ))
+                })?;
+
+            size += get_number(&stats, "storageSize")? + get_number(&stats, "totalIndexSize")?;
+        }
+
+        Ok(size as u64)
+    }
+
+    pub async fn reset(&self) -> Result<()> {
+        self.depth_history.drop().await.map_err(|e| {
+            Error::OperationFailed(format!("Failed to drop depth history collection: {}", e))
+        })?;
+
+        self.rune_pule_history.drop().await.map_err(|e| {
+            Error::OperationFailed(format!(
+                "Failed to drop rune pool history collection: {}",
+                e
+            ))
+        })?;
+
+        // dropping a collection drops its indexes as well
+        self.ensure_indexes().await?;
+
+        Ok(())
+    }
 }
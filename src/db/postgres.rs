@@ -1,112 +1,358 @@
-use std::env;
+use std::str::FromStr;
 
-use dotenv::dotenv;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Row};
+use serde::Deserialize;
+use sqlx::{
+    postgres::{PgArguments, PgConnectOptions, PgPoolOptions},
+    query::Query,
+    Pool, Postgres, Row,
+};
 
 use crate::{
-    models::{depth_history_model::DepthHistory, rune_pool_history_model::RunePoolHistory},
-    utils::types::{Error, Result},
+    models::{
+        aggregation_model::{
+            DailyDepthAggregate, DepthHistoryAggregates, WeeklyUnitsAggregate, SECONDS_PER_DAY,
+            SECONDS_PER_WEEK,
+        },
+        depth_history_model::DepthHistory,
+        rune_pool_history_model::RunePoolHistory,
+    },
+    utils::{
+        durability::Durability,
+        types::{Error, Result},
+    },
 };
 
+#[derive(Debug, Clone, Copy)]
+pub enum PostgresIndexType {
+    BTree,
+    Brin,
+}
+
+impl PostgresIndexType {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            PostgresIndexType::BTree => "btree",
+            PostgresIndexType::Brin => "brin",
+        }
+    }
+}
+
+// The `[postgres]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PostgresConfig {
+    pub url: String,
+    pub max_connections: u32,
+    // overrides the top level durability
+    pub durability: Option<Durability>,
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        PostgresConfig {
+            url: String::new(),
+            max_connections: 5,
+            durability: None,
+        }
+    }
+}
+
+impl PostgresConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.url.is_empty() {
+            errors.push("postgres.url is not set".to_string());
+        } else if let Err(err) = PgConnectOptions::from_str(&self.url) {
+            errors.push(format!("postgres.url is invalid: {}", err));
+        }
+        if self.max_connections == 0 {
+            errors.push("postgres.max_connections must be at least 1".to_string());
+        }
+    }
+}
+
 pub struct PostgreSQL {
     pub pool: Pool<Postgres>,
+    pub durability: Durability,
 }
 
 impl PostgreSQL {
-    pub async fn init() -> Result<Self> {
-        dotenv().ok();
-
-        let uri = match env::var("POSGRES_DATABASE_URL") {
-            Ok(v) => v.to_string(),
-            Err(_) => {
-                return Err(Error::DataBaseConnectionFailed(
-                    "Unable to read mongodb uri.".to_string(),
+    pub async fn init(config: &PostgresConfig, durability: Durability) -> Result<Self> {
+        let mut connect_options = PgConnectOptions::from_str(&config.url).map_err(|e| {
+            Error::DataBaseConnectionFailed(format!("Invalid Postgres database url: {}", e))
+        })?;
+        // applied to every pooled connection, so it covers autocommit and transactional inserts
+        match durability {
+            Durability::Default => {}
+            // rejected by the config validation, there is no per-session way to skip the WAL
+            Durability::Off => {
+                return Err(Error::InvalidConfiguration(
+                    "Postgres cannot run with durability off".to_string(),
                 ))
             }
-        };
+            Durability::Buffered => {
+                connect_options = connect_options.options([("synchronous_commit", "off")]);
+            }
+            Durability::Sync => {
+                connect_options = connect_options.options([("synchronous_commit", "on")]);
+            }
+        }
 
         let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .connect(&uri)
+            .max_connections(config.max_connections)
+            .connect_with(connect_options)
             .await
-            .map_err(|e| e)
-            .unwrap();
+            .map_err(|e| {
+                Error::DataBaseConnectionFailed(format!("Failed to connect to Postgres: {}", e))
+            })?;
+
+        let postgres = PostgreSQL { pool, durability };
 
-        let postgres = PostgreSQL { pool };
+        // `synchronous_commit = on` is also the server default, unlike `default` a sync
+        // connection refuses a server that acknowledges commits without flushing them
+        if durability == Durability::Sync {
+            postgres.require_fsync().await?;
+        }
 
-        postgres.ensure_table_exists().await?;
+        postgres.run_migrations().await?;
 
         Ok(postgres)
     }
 
-    pub async fn ensure_table_exists(&self) -> Result<()> {
+    async fn require_fsync(&self) -> Result<()> {
+        let fsync: String = sqlx::query_scalar("SHOW fsync")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| {
+                Error::DataBaseConnectionFailed(format!("Failed to read Postgres fsync: {}", e))
+            })?;
+
+        if fsync != "on" {
+            return Err(Error::InvalidConfiguration(format!(
+                "Postgres durability sync needs fsync = on, the server runs with fsync = {}",
+                fsync
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Applies the pending migrations from the migrations directory, applied versions are
+    // tracked in the _sqlx_migrations table.
+    pub async fn run_migrations(&self) -> Result<()> {
+        sqlx::migrate!("./migrations")
+            .run(&self.pool)
+            .await
+            .map_err(|e| Error::DataBaseCreationFailed(format!("Migration failed: {}", e)))?;
+
+        Ok(())
+    }
+
+    // Secondary indexes on the index experiment table, start_time is already covered by the
+    // primary key.
+    pub async fn create_secondary_indexes(&self, index_type: PostgresIndexType) -> Result<()> {
+        for (name, column) in [
+            ("depth_history_index_experiment_end_time_idx", "end_time"),
+            (
+                "depth_history_index_experiment_asset_price_usd_idx",
+                "asset_price_usd",
+            ),
+        ] {
+            sqlx::query(&format!(
+                "CREATE INDEX IF NOT EXISTS {} ON depth_history_index_experiment USING {} ({})",
+                name,
+                index_type.as_sql(),
+                column
+            ))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                Error::DataBaseCreationFailed(format!("Index {} creation failed: {}", name, e))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn drop_secondary_indexes(&self) -> Result<()> {
         sqlx::query(
-            r#"
-        CREATE TABLE IF NOT EXISTS depth_history (
-            start_time DOUBLE PRECISION NOT NULL,
-            end_time DOUBLE PRECISION NOT NULL,
-            asset_depth DOUBLE PRECISION NOT NULL,
-            rune_depth DOUBLE PRECISION NOT NULL,
-            asset_price DOUBLE PRECISION NOT NULL,
-            asset_price_usd DOUBLE PRECISION NOT NULL,
-            liquidity_units DOUBLE PRECISION NOT NULL,
-            members_count DOUBLE PRECISION NOT NULL,
-            synth_units DOUBLE PRECISION NOT NULL,
-            synth_supply DOUBLE PRECISION NOT NULL,
-            units DOUBLE PRECISION NOT NULL,
-            luvi DOUBLE PRECISION NOT NULL
-        )
-        "#,
+            "DROP INDEX IF EXISTS depth_history_index_experiment_end_time_idx, \
+             depth_history_index_experiment_asset_price_usd_idx",
         )
         .execute(&self.pool)
         .await
-        .map_err(|e| Error::DataBaseCreationFailed(format!("Table creation failed: {}", e)))?;
+        .map_err(|e| Error::OperationFailed(format!("Failed to drop indexes: {}", e)))?;
 
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS rune_pool_history (
-                start_time DOUBLE PRECISION NOT NULL,
-                end_time DOUBLE PRECISION NOT NULL,
-                count DOUBLE PRECISION NOT NULL,
-                units DOUBLE PRECISION NOT NULL
-            )
-            "#,
+        Ok(())
+    }
+
+    fn insert_depth_history_query(
+        depth_history: &DepthHistory,
+    ) -> Query<'_, Postgres, PgArguments> {
+        Self::bind_depth_history(
+            sqlx::query(
+                r#"
+        INSERT INTO depth_history (
+            start_time, end_time, asset_depth, rune_depth, asset_price, asset_price_usd,
+            liquidity_units, members_count, synth_units, synth_supply, units, luvi
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#,
+            ),
+            depth_history,
+        )
+    }
+
+    fn bind_depth_history<'q>(
+        query: Query<'q, Postgres, PgArguments>,
+        depth_history: &DepthHistory,
+    ) -> Query<'q, Postgres, PgArguments> {
+        query
+            .bind(depth_history.start_time)
+            .bind(depth_history.end_time)
+            .bind(depth_history.asset_depth)
+            .bind(depth_history.rune_depth)
+            .bind(depth_history.asset_price)
+            .bind(depth_history.asset_price_usd)
+            .bind(depth_history.liquidity_units)
+            .bind(depth_history.members_count)
+            .bind(depth_history.synth_units)
+            .bind(depth_history.synth_supply)
+            .bind(depth_history.units)
+            .bind(depth_history.luvi)
+    }
+
+    // The index experiment writes to its own table, the benchmark data stays untouched.
+    pub async fn insert_index_experiment_record(&self, depth_history: &DepthHistory) -> Result<()> {
+        Self::bind_depth_history(
+            sqlx::query(
+                r#"
+        INSERT INTO depth_history_index_experiment (
+            start_time, end_time, asset_depth, rune_depth, asset_price, asset_price_usd,
+            liquidity_units, members_count, synth_units, synth_supply, units, luvi
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        "#,
+            ),
+            depth_history,
         )
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            Error::DataBaseCreationFailed(format!("Rune pool history table creation failed: {}", e))
+            Error::DataBaseInsertionFailed(format!("Index experiment insert failed: {}", e))
         })?;
 
         Ok(())
     }
 
+    pub async fn clear_index_experiment(&self) -> Result<()> {
+        sqlx::query("TRUNCATE TABLE depth_history_index_experiment")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                Error::OperationFailed(format!("Failed to truncate index experiment table: {}", e))
+            })?;
+
+        Ok(())
+    }
+
     pub async fn insert_depth_history(&self, depth_history: &DepthHistory) -> Result<()> {
-        sqlx::query(
-            r#"
+        Self::insert_depth_history_query(depth_history)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Depth history insert failed: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    // All records go in through one BEGIN/COMMIT; a failed insert rolls the whole batch back.
+    pub async fn insert_depth_history_in_transaction(
+        &self,
+        records: &[DepthHistory],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to begin transaction: {}", e))
+        })?;
+
+        for depth_history in records {
+            Self::insert_depth_history_query(depth_history)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    Error::DataBaseInsertionFailed(format!("Depth history insert failed: {}", e))
+                })?;
+        }
+
+        tx.commit().await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to commit depth history: {}", e))
+        })
+    }
+
+    pub async fn upsert_depth_history(&self, depth_history: &DepthHistory) -> Result<()> {
+        Self::bind_depth_history(
+            sqlx::query(
+                r#"
         INSERT INTO depth_history (
             start_time, end_time, asset_depth, rune_depth, asset_price, asset_price_usd,
             liquidity_units, members_count, synth_units, synth_supply, units, luvi
         )
         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (start_time) DO UPDATE SET
+            end_time = EXCLUDED.end_time,
+            asset_depth = EXCLUDED.asset_depth,
+            rune_depth = EXCLUDED.rune_depth,
+            asset_price = EXCLUDED.asset_price,
+            asset_price_usd = EXCLUDED.asset_price_usd,
+            liquidity_units = EXCLUDED.liquidity_units,
+            members_count = EXCLUDED.members_count,
+            synth_units = EXCLUDED.synth_units,
+            synth_supply = EXCLUDED.synth_supply,
+            units = EXCLUDED.units,
+            luvi = EXCLUDED.luvi
         "#,
+            ),
+            depth_history,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Depth history upsert failed: {}", e))
+        })?;
+
+        Ok(())
+    }
+
+    pub async fn scale_depth_history_price(&self, start_time: i64, factor: f64) -> Result<()> {
+        sqlx::query(
+            "UPDATE depth_history SET asset_price_usd = asset_price_usd * $1 WHERE start_time = $2",
+        )
+        .bind(factor)
+        .bind(start_time)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Depth history update failed: {}", e))
+        })?;
+
+        Ok(())
+    }
+
+    pub async fn scale_depth_history_prices_from(
+        &self,
+        start_time: i64,
+        factor: f64,
+    ) -> Result<()> {
+        sqlx::query(
+            "UPDATE depth_history SET asset_price_usd = asset_price_usd * $1 \
+             WHERE start_time >= $2",
         )
-        .bind(depth_history.start_time)
-        .bind(depth_history.end_time)
-        .bind(depth_history.asset_depth)
-        .bind(depth_history.rune_depth)
-        .bind(depth_history.asset_price)
-        .bind(depth_history.asset_price_usd)
-        .bind(depth_history.liquidity_units)
-        .bind(depth_history.members_count)
-        .bind(depth_history.synth_units)
-        .bind(depth_history.synth_supply)
-        .bind(depth_history.units)
-        .bind(depth_history.luvi)
+        .bind(factor)
+        .bind(start_time)
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            Error::DataBaseInsertionFailed(format!("Depth history insert failed: {}", e))
+            Error::DataBaseInsertionFailed(format!("Depth history bulk update failed: {}", e))
         })?;
 
         Ok(())
@@ -155,6 +401,155 @@ impl PostgreSQL {
         Ok(records)
     }
 
+    pub async fn read_depth_history_start_times(&self) -> Result<Vec<i64>> {
+        let rows = sqlx::query("SELECT start_time FROM depth_history")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| {
+                Error::DataBaseReadFailed(format!(
+                    "Failed to read depth history start times: {}",
+                    e
+                ))
+            })?;
+
+        Ok(rows.into_iter().map(|row| row.get("start_time")).collect())
+    }
+
+    pub async fn read_depth_history_by_start_time(
+        &self,
+        start_time: i64,
+    ) -> Result<Option<DepthHistory>> {
+        let row = sqlx::query(
+            r#"
+        SELECT 
+            start_time, 
+            end_time, 
+            asset_depth, 
+            rune_depth, 
+            asset_price, 
+            asset_price_usd,
+            liquidity_units, 
+            members_count, 
+            synth_units, 
+            synth_supply, 
+            units, 
+            luvi
+        FROM depth_history
+        WHERE start_time = $1
+        LIMIT 1
+        "#,
+        )
+        .bind(start_time)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to look up depth history: {}", e))
+        })?;
+
+        Ok(row.map(|row| DepthHistory {
+            start_time: row.get("start_time"),
+            end_time: row.get("end_time"),
+            asset_depth: row.get("asset_depth"),
+            rune_depth: row.get("rune_depth"),
+            asset_price: row.get("asset_price"),
+            asset_price_usd: row.get("asset_price_usd"),
+            liquidity_units: row.get("liquidity_units"),
+            members_count: row.get("members_count"),
+            synth_units: row.get("synth_units"),
+            synth_supply: row.get("synth_supply"),
+            units: row.get("units"),
+            luvi: row.get("luvi"),
+        }))
+    }
+
+    // Range and price queries of the index experiment, on its own table.
+    pub async fn read_index_experiment_by_end_time_range(
+        &self,
+        from_end_time: i64,
+        to_end_time: i64,
+    ) -> Result<Vec<DepthHistory>> {
+        sqlx::query_as::<_, DepthHistory>(
+            "SELECT * FROM depth_history_index_experiment WHERE end_time BETWEEN $1 AND $2",
+        )
+        .bind(from_end_time)
+        .bind(to_end_time)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to read depth history by end time: {}", e))
+        })
+    }
+
+    pub async fn read_index_experiment_by_min_price(
+        &self,
+        min_asset_price_usd: f64,
+    ) -> Result<Vec<DepthHistory>> {
+        sqlx::query_as::<_, DepthHistory>(
+            "SELECT * FROM depth_history_index_experiment WHERE asset_price_usd >= $1",
+        )
+        .bind(min_asset_price_usd)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to read depth history by price: {}", e))
+        })
+    }
+
+    pub async fn aggregate_depth_history(&self) -> Result<DepthHistoryAggregates> {
+        let daily_rows = sqlx::query(
+            r#"
+        SELECT
+            floor(start_time::numeric / $1)::BIGINT AS day,
+            AVG(asset_price_usd) AS avg_asset_price_usd,
+            MAX(rune_depth) AS max_rune_depth
+        FROM depth_history
+        GROUP BY day
+        ORDER BY day
+        "#,
+        )
+        .bind(SECONDS_PER_DAY)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to aggregate daily depth history: {}", e))
+        })?;
+
+        let weekly_rows = sqlx::query(
+            r#"
+        SELECT
+            floor(start_time::numeric / $1)::BIGINT AS week,
+            SUM(units)::BIGINT AS total_units
+        FROM depth_history
+        GROUP BY week
+        ORDER BY week
+        "#,
+        )
+        .bind(SECONDS_PER_WEEK)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to aggregate weekly depth history: {}", e))
+        })?;
+
+        Ok(DepthHistoryAggregates {
+            daily: daily_rows
+                .into_iter()
+                .map(|row| DailyDepthAggregate {
+                    day: row.get("day"),
+                    avg_asset_price_usd: row.get("avg_asset_price_usd"),
+                    max_rune_depth: row.get("max_rune_depth"),
+                })
+                .collect(),
+            weekly: weekly_rows
+                .into_iter()
+                .map(|row| WeeklyUnitsAggregate {
+                    week: row.get("week"),
+                    total_units: row.get("total_units"),
+                })
+                .collect(),
+        })
+    }
+
     pub async fn read_rune_pool_history(&self) -> Result<Vec<RunePoolHistory>> {
         let rows = sqlx::query(
             r#"
@@ -185,13 +580,70 @@ impl PostgreSQL {
         Ok(records)
     }
 
+    fn insert_rune_pool_history_query(
+        pool_history: &RunePoolHistory,
+    ) -> Query<'_, Postgres, PgArguments> {
+        sqlx::query(
+            r#"
+                INSERT INTO rune_pool_history (
+                start_time,end_time,count,units
+                )
+                VALUES ($1,$2,$3,$4)
+            "#,
+        )
+        .bind(pool_history.start_time)
+        .bind(pool_history.end_time)
+        .bind(pool_history.count)
+        .bind(pool_history.units)
+    }
+
     pub async fn insert_rune_pool_history(&self, pool_history: &RunePoolHistory) -> Result<()> {
+        Self::insert_rune_pool_history_query(pool_history)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                Error::DataBaseInsertionFailed(format!("Rune pool history insert failed: {}", e))
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn insert_rune_pool_history_in_transaction(
+        &self,
+        records: &[RunePoolHistory],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to begin transaction: {}", e))
+        })?;
+
+        for pool_history in records {
+            Self::insert_rune_pool_history_query(pool_history)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    Error::DataBaseInsertionFailed(format!(
+                        "Rune pool history insert failed: {}",
+                        e
+                    ))
+                })?;
+        }
+
+        tx.commit().await.map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to commit rune pool history: {}", e))
+        })
+    }
+
+    pub async fn upsert_rune_pool_history(&self, pool_history: &RunePoolHistory) -> Result<()> {
         sqlx::query(
             r#"
                 INSERT INTO rune_pool_history (
                 start_time,end_time,count,units
                 )
                 VALUES ($1,$2,$3,$4)
+                ON CONFLICT (start_time) DO UPDATE SET
+                end_time = EXCLUDED.end_time,
+                count = EXCLUDED.count,
+                units = EXCLUDED.units
             "#,
         )
         .bind(pool_history.start_time)
@@ -201,9 +653,31 @@ impl PostgreSQL {
         .execute(&self.pool)
         .await
         .map_err(|e| {
-            Error::DataBaseInsertionFailed(format!("Rune pool history insert failed: {}", e))
+            Error::DataBaseInsertionFailed(format!("Rune pool history upsert failed: {}", e))
         })?;
 
         Ok(())
     }
+
+    // Table, index and TOAST bytes of both tables.
+    pub async fn storage_size(&self) -> Result<u64> {
+        let size: i64 = sqlx::query_scalar(
+            "SELECT (pg_total_relation_size('depth_history') \
+             + pg_total_relation_size('rune_pool_history'))::BIGINT",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| Error::DataBaseReadFailed(format!("Failed to read table sizes: {}", e)))?;
+
+        Ok(size as u64)
+    }
+
+    pub async fn reset(&self) -> Result<()> {
+        sqlx::query("TRUNCATE TABLE depth_history, rune_pool_history")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| Error::OperationFailed(format!("Failed to truncate tables: {}", e)))?;
+
+        Ok(())
+    }
 }
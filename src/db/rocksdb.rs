@@ -1,24 +1,357 @@
-use rocksdb::DB;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+
+use rocksdb::{
+    BlockBasedOptions, Cache, DBCompactionStyle, DBCompressionType, IteratorMode, Options,
+    TransactionDB, TransactionDBOptions, TransactionOptions, WriteBatch, WriteOptions, DB,
+};
+
+use serde::Deserialize;
 
 use crate::{
+    config::describe,
     models::{depth_history_model::DepthHistory, rune_pool_history_model::RunePoolHistory},
-    utils::types::{Error, Result},
+    utils::{
+        disk_usage::directory_size,
+        durability::Durability,
+        kv_keys::{
+            depth_history_start_time, is_legacy_key, is_legacy_record_key, migrated_key,
+            DEPTH_HISTORY_PREFIX,
+        },
+        types::{Error, Result},
+    },
 };
 
+// The `[rocksdb]` section of the config file: where the database lives and its storage engine
+// options. Unset options keep the RocksDB defaults.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RocksDBConfig {
+    pub path: String,
+    pub block_cache_size: Option<usize>,
+    pub write_buffer_size: Option<usize>,
+    pub compression: Option<String>,
+    pub bloom_filter_bits_per_key: Option<f64>,
+    pub max_open_files: Option<i32>,
+    pub compaction_style: Option<String>,
+    // overrides the top level durability
+    pub durability: Option<Durability>,
+}
+
+impl Default for RocksDBConfig {
+    fn default() -> Self {
+        RocksDBConfig {
+            path: "data/rocksdb".to_string(),
+            block_cache_size: None,
+            write_buffer_size: None,
+            compression: None,
+            bloom_filter_bits_per_key: None,
+            max_open_files: None,
+            compaction_style: None,
+            durability: None,
+        }
+    }
+}
+
+impl RocksDBConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.path.is_empty() {
+            errors.push("rocksdb.path is not set".to_string());
+        }
+        if self.block_cache_size == Some(0) || self.write_buffer_size == Some(0) {
+            errors.push("rocksdb cache and buffer sizes must be positive".to_string());
+        }
+        if let Err(err) = self.compression_type() {
+            errors.push(format!("rocksdb.compression: {:?}", err));
+        }
+        if let Err(err) = self.compaction_style() {
+            errors.push(format!("rocksdb.compaction_style: {:?}", err));
+        }
+    }
+
+    fn compression_type(&self) -> Result<Option<DBCompressionType>> {
+        let compression = match &self.compression {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        match compression.to_lowercase().as_str() {
+            "none" => Ok(Some(DBCompressionType::None)),
+            "snappy" => Ok(Some(DBCompressionType::Snappy)),
+            "zlib" => Ok(Some(DBCompressionType::Zlib)),
+            "bz2" => Ok(Some(DBCompressionType::Bz2)),
+            "lz4" => Ok(Some(DBCompressionType::Lz4)),
+            "lz4hc" => Ok(Some(DBCompressionType::Lz4hc)),
+            "zstd" => Ok(Some(DBCompressionType::Zstd)),
+            other => Err(Error::OperationFailed(format!(
+                "Unknown RocksDB compression {:?}, expected none, snappy, zlib, bz2, lz4, lz4hc or zstd.",
+                other
+            ))),
+        }
+    }
+
+    fn compaction_style(&self) -> Result<Option<DBCompactionStyle>> {
+        let style = match &self.compaction_style {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        match style.to_lowercase().as_str() {
+            "level" => Ok(Some(DBCompactionStyle::Level)),
+            "universal" => Ok(Some(DBCompactionStyle::Universal)),
+            "fifo" => Ok(Some(DBCompactionStyle::Fifo)),
+            other => Err(Error::OperationFailed(format!(
+                "Unknown RocksDB compaction style {:?}, expected level, universal or fifo.",
+                other
+            ))),
+        }
+    }
+
+    fn options(&self) -> Result<Options> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+
+        if let Some(size) = self.write_buffer_size {
+            options.set_write_buffer_size(size);
+        }
+        if let Some(compression) = self.compression_type()? {
+            options.set_compression_type(compression);
+        }
+        if let Some(files) = self.max_open_files {
+            options.set_max_open_files(files);
+        }
+        if let Some(style) = self.compaction_style()? {
+            options.set_compaction_style(style);
+        }
+
+        if self.block_cache_size.is_some() || self.bloom_filter_bits_per_key.is_some() {
+            let mut table_options = BlockBasedOptions::default();
+            if let Some(size) = self.block_cache_size {
+                table_options.set_block_cache(&Cache::new_lru_cache(size));
+            }
+            if let Some(bits) = self.bloom_filter_bits_per_key {
+                table_options.set_bloom_filter(bits, false);
+            }
+            options.set_block_based_table_factory(&table_options);
+        }
+
+        Ok(options)
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "path={}, block_cache_size={}, write_buffer_size={}, compression={}, bloom_filter_bits_per_key={}, max_open_files={}, compaction_style={}",
+            self.path,
+            describe(&self.block_cache_size),
+            describe(&self.write_buffer_size),
+            describe(&self.compression),
+            describe(&self.bloom_filter_bits_per_key),
+            describe(&self.max_open_files),
+            describe(&self.compaction_style),
+        )
+    }
+}
+
+type Entry = std::result::Result<(Box<[u8]>, Box<[u8]>), rocksdb::Error>;
+
+// Autocommitted writes go to a plain DB. A TransactionDB is only opened for the transactional
+// insert mode, its plain puts run as single-write transactions and would slow down the
+// autocommit numbers. Only one of them can hold the database directory at a time.
+enum Handle {
+    Plain(DB),
+    Transactional(TransactionDB),
+}
+
+impl Handle {
+    fn is_transactional(&self) -> bool {
+        matches!(self, Handle::Transactional(_))
+    }
+
+    fn get(&self, key: &[u8]) -> std::result::Result<Option<Vec<u8>>, rocksdb::Error> {
+        match self {
+            Handle::Plain(db) => db.get(key),
+            Handle::Transactional(db) => db.get(key),
+        }
+    }
+
+    fn delete(&self, key: &[u8]) -> std::result::Result<(), rocksdb::Error> {
+        match self {
+            Handle::Plain(db) => db.delete(key),
+            Handle::Transactional(db) => db.delete(key),
+        }
+    }
+
+    fn iterator<'a>(&'a self, mode: IteratorMode<'a>) -> Box<dyn Iterator<Item = Entry> + 'a> {
+        match self {
+            Handle::Plain(db) => Box::new(db.iterator(mode)),
+            Handle::Transactional(db) => Box::new(db.iterator(mode)),
+        }
+    }
+}
+
 pub struct RocksDB {
-    pub db: DB,
+    // None while the database is being reopened, or after a reopen failed
+    db: RwLock<Option<Arc<Handle>>>,
+    // the kind of handle opened when the database is opened again
+    transactional: AtomicBool,
+    pub durability: Durability,
+    pub config: RocksDBConfig,
+    write_options: WriteOptions,
 }
 
 impl RocksDB {
-    pub async fn init() -> Result<Self> {
-        let db = DB::open_default("data/rocksdb").map_err(|e| {
+    pub async fn init(config: &RocksDBConfig, durability: Durability) -> Result<Self> {
+        let db = Self::open(config, false)?;
+        if let Handle::Plain(plain) = &db {
+            Self::migrate_legacy_keys(plain)?;
+        }
+
+        let mut write_options = WriteOptions::default();
+        match durability {
+            Durability::Default | Durability::Buffered => {}
+            Durability::Off => write_options.disable_wal(true),
+            Durability::Sync => write_options.set_sync(true),
+        }
+
+        Ok(RocksDB {
+            db: RwLock::new(Some(Arc::new(db))),
+            transactional: AtomicBool::new(false),
+            durability,
+            config: config.clone(),
+            write_options,
+        })
+    }
+
+    fn open(config: &RocksDBConfig, transactional: bool) -> Result<Handle> {
+        let options = config.options()?;
+        let handle = if transactional {
+            TransactionDB::open(&options, &TransactionDBOptions::default(), &config.path)
+                .map(Handle::Transactional)
+        } else {
+            DB::open(&options, &config.path).map(Handle::Plain)
+        };
+
+        handle.map_err(|e| {
             Error::DataBaseConnectionFailed(format!("Failed to open RocksDB: {:?}", e))
-        })?;
-        Ok(RocksDB { db })
+        })
+    }
+
+    // Moves records stored under the old unprefixed keys to their dataset prefix and drops the
+    // old index experiment entries, in one atomic batch. A record already stored under its
+    // prefixed key is newer and is kept.
+    fn migrate_legacy_keys(db: &DB) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for item in db.iterator(IteratorMode::Start) {
+            let (key, val) = item.map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to read item in RocksDB: {:?}", e))
+            })?;
+
+            if !is_legacy_key(&key) {
+                continue;
+            }
+
+            if is_legacy_record_key(&key) {
+                if let Some(new_key) = migrated_key(&val) {
+                    let stored = db.get(&new_key).map_err(|e| {
+                        Error::DataBaseReadFailed(format!(
+                            "Failed to retrieve value in RocksDB: {:?}",
+                            e
+                        ))
+                    })?;
+
+                    if stored.is_none() {
+                        batch.put(&new_key, &val);
+                    }
+                }
+            }
+
+            batch.delete(&key);
+        }
+
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        db.write(batch).map_err(|e| {
+            Error::OperationFailed(format!("Failed to migrate legacy keys in RocksDB: {:?}", e))
+        })
+    }
+
+    // Closes the open handle and opens the database again as the requested kind. On failure the
+    // handle stays empty and the next use opens the database again.
+    fn replace(&self, db: &mut Option<Arc<Handle>>, transactional: bool) -> Result<Arc<Handle>> {
+        if let Some(current) = db.take() {
+            match Arc::try_unwrap(current) {
+                // closes the database, its lock file is released before the open below
+                Ok(_) => {}
+                Err(current) => {
+                    *db = Some(current);
+                    return Err(Error::OperationFailed(
+                        "RocksDB is still in use and cannot be reopened".to_string(),
+                    ));
+                }
+            }
+        }
+
+        self.transactional.store(transactional, Ordering::SeqCst);
+        let opened = Arc::new(Self::open(&self.config, transactional)?);
+        *db = Some(opened.clone());
+
+        Ok(opened)
+    }
+
+    fn lock(&self) -> Result<std::sync::RwLockWriteGuard<'_, Option<Arc<Handle>>>> {
+        self.db
+            .write()
+            .map_err(|_| Error::OperationFailed("RocksDB handle lock is poisoned".to_string()))
+    }
+
+    // Opens the database again when a reopen left it closed, so a failed reopen does not leave
+    // RocksDB unusable until the process restarts.
+    fn db(&self) -> Result<Arc<Handle>> {
+        let mut db = self.lock()?;
+
+        match &*db {
+            Some(current) => Ok(current.clone()),
+            None => self.replace(&mut db, self.transactional.load(Ordering::SeqCst)),
+        }
+    }
+
+    // The open handle if it is of the requested kind, otherwise the database is reopened as it.
+    fn db_as(&self, transactional: bool) -> Result<Arc<Handle>> {
+        let mut db = self.lock()?;
+
+        match &*db {
+            Some(current) if current.is_transactional() == transactional => Ok(current.clone()),
+            _ => self.replace(&mut db, transactional),
+        }
+    }
+
+    // Switches between the plain DB and the TransactionDB before an ingest, so the reopen is not
+    // part of the measured insert time.
+    pub async fn use_transactions(&self, transactional: bool) -> Result<()> {
+        self.db_as(transactional).map(|_| ())
+    }
+
+    // Closes and reopens the database, dropping its block cache and memtables so the next reads
+    // come from disk (or the OS page cache). Fails if another request still holds the database.
+    pub async fn reopen(&self) -> Result<()> {
+        let mut db = self.lock()?;
+        let transactional = match &*db {
+            Some(current) => current.is_transactional(),
+            None => self.transactional.load(Ordering::SeqCst),
+        };
+
+        self.replace(&mut db, transactional).map(|_| ())
     }
 
     pub async fn insert_data(&self, key: Vec<u8>, val: Vec<u8>) -> Result<()> {
-        self.db.put(key, val).map_err(|e| {
+        let Handle::Plain(db) = &*self.db_as(false)? else {
+            unreachable!("db_as(false) opens a plain DB");
+        };
+        db.put_opt(key, val, &self.write_options).map_err(|e| {
             Error::DataBaseInsertionFailed(format!(
                 "Failed to insert depth history in RocksDB: {:?}",
                 e
@@ -28,9 +361,125 @@ impl RocksDB {
         Ok(())
     }
 
-    pub async fn read_data(&self) -> Result<()> {
-        let iter = self.db.iterator(rocksdb::IteratorMode::Start);
+    // Writes every entry in a single transaction, nothing is visible until the commit.
+    pub async fn insert_data_in_transaction(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let handle = self.db_as(true)?;
+        let Handle::Transactional(db) = &*handle else {
+            unreachable!("db_as(true) opens a TransactionDB");
+        };
+        let txn = db.transaction_opt(&self.write_options, &TransactionOptions::default());
+        for (key, val) in entries {
+            txn.put(key, val).map_err(|e| {
+                Error::DataBaseInsertionFailed(format!(
+                    "Failed to insert data in RocksDB transaction: {:?}",
+                    e
+                ))
+            })?;
+        }
+
+        txn.commit().map_err(|e| {
+            Error::DataBaseInsertionFailed(format!("Failed to commit RocksDB transaction: {:?}", e))
+        })
+    }
+
+    pub async fn get_data(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let db = self.db()?;
+        db.get(&key).map_err(|e| {
+            Error::DataBaseReadFailed(format!("Failed to retrieve value in RocksDB: {:?}", e))
+        })
+    }
+
+    pub async fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let db = self.db()?;
+        let iter = db.iterator(IteratorMode::From(prefix, rocksdb::Direction::Forward));
+
+        let mut values = Vec::new();
+        for item in iter {
+            let (key, val) = item.map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to read item in RocksDB: {:?}", e))
+            })?;
+
+            if !key.starts_with(prefix) {
+                break;
+            }
+
+            values.push(val.to_vec());
+        }
+
+        Ok(values)
+    }
+
+    pub async fn read_depth_history_start_times(&self) -> Result<Vec<i64>> {
+        let db = self.db()?;
+        let iter = db.iterator(IteratorMode::From(
+            DEPTH_HISTORY_PREFIX,
+            rocksdb::Direction::Forward,
+        ));
+
+        let mut start_times = Vec::new();
+        for item in iter {
+            let (key, _) = item.map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to read item in RocksDB: {:?}", e))
+            })?;
+
+            if !key.starts_with(DEPTH_HISTORY_PREFIX) {
+                break;
+            }
+
+            start_times.extend(depth_history_start_time(&key));
+        }
+
+        Ok(start_times)
+    }
+
+    // Values of all keys in [from, to).
+    pub async fn scan_range(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let db = self.db()?;
+        let iter = db.iterator(IteratorMode::From(from, rocksdb::Direction::Forward));
+
+        let mut values = Vec::new();
+        for item in iter {
+            let (key, val) = item.map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to read item in RocksDB: {:?}", e))
+            })?;
+
+            if key.as_ref() >= to {
+                break;
+            }
+
+            values.push(val.to_vec());
+        }
+
+        Ok(values)
+    }
+
+    pub async fn delete_prefix(&self, prefix: &[u8]) -> Result<()> {
+        let db = self.db()?;
+        let iter = db.iterator(IteratorMode::From(prefix, rocksdb::Direction::Forward));
+
+        for item in iter {
+            let (key, _) = item.map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to read item in RocksDB: {:?}", e))
+            })?;
+
+            if !key.starts_with(prefix) {
+                break;
+            }
+
+            db.delete(&key).map_err(|e| {
+                Error::OperationFailed(format!("Failed to delete key in RocksDB: {:?}", e))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    // Reads every key and its value, returns the number of entries read.
+    pub async fn read_data(&self) -> Result<usize> {
+        let db = self.db()?;
+        let iter = db.iterator(IteratorMode::Start);
 
+        let mut entries = 0;
         for item in iter {
             let (key, _) = item.map_err(|e| {
                 Error::DataBaseReadFailed(format!("Failed to read item in RocksDB: {:?}", e))
@@ -40,9 +489,32 @@ impl RocksDB {
                 Error::DataBaseReadFailed(format!("Failed to parse key in RocksDB: {:?}", e))
             })?;
 
-            let val = self.db.get(&key_str).map_err(|e| {
+            db.get(key_str.as_bytes()).map_err(|e| {
                 Error::DataBaseReadFailed(format!("Failed to retrieve value in RocksDB: {:?}", e))
             })?;
+            entries += 1;
+        }
+
+        Ok(entries)
+    }
+
+    // Bytes on disk, including the WAL for writes that are not flushed to SST files yet.
+    pub async fn storage_size(&self) -> Result<u64> {
+        directory_size(&self.config.path)
+    }
+
+    pub async fn reset(&self) -> Result<()> {
+        let db = self.db()?;
+        let iter = db.iterator(IteratorMode::Start);
+
+        for item in iter {
+            let (key, _) = item.map_err(|e| {
+                Error::DataBaseReadFailed(format!("Failed to read item in RocksDB: {:?}", e))
+            })?;
+
+            db.delete(&key).map_err(|e| {
+                Error::OperationFailed(format!("Failed to delete key in RocksDB: {:?}", e))
+            })?;
         }
 
         Ok(())
@@ -1,51 +1,236 @@
+use crate::models::aggregation_model::{
+    DailyDepthAggregate, DepthHistoryAggregates, WeeklyUnitsAggregate, SECONDS_PER_DAY,
+    SECONDS_PER_WEEK,
+};
 use crate::models::depth_history_model::DepthHistory;
 use crate::models::rune_pool_history_model::RunePoolHistory;
+use crate::utils::disk_usage::directory_size;
 use crate::utils::types::{Error, Result};
-use dotenv::dotenv;
-use std::env;
+use serde::{Deserialize, Serialize};
 use surrealdb::engine::any::{self, Any};
-use surrealdb::opt::auth::Root;
+use surrealdb::opt::auth::{Database, Namespace, Root};
 use surrealdb::Surreal;
 use surrealdb::{self};
 
+// Embedded engines that run inside our process next to the SurrealDB server, listed in
+// `surrealdb.embedded_engines` (e.g. `["mem", "surrealkv"]`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SurrealEngine {
+    #[serde(rename = "mem", alias = "memory")]
+    Memory,
+    #[serde(rename = "surrealkv")]
+    SurrealKv,
+    #[serde(rename = "rocksdb")]
+    RocksDb,
+}
+
+impl SurrealEngine {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SurrealEngine::Memory => "mem",
+            SurrealEngine::SurrealKv => "surrealkv",
+            SurrealEngine::RocksDb => "rocksdb",
+        }
+    }
+
+    // SurrealDB links its own RocksDB, it is only built in with the surrealdb-rocksdb feature.
+    pub fn is_available(&self) -> bool {
+        *self != SurrealEngine::RocksDb || cfg!(feature = "surrealdb-rocksdb")
+    }
+
+    fn url(&self, config: &SurrealConfig) -> String {
+        match self.path(config) {
+            Some(path) => format!("{}://{}", self.as_str(), path),
+            None => "mem://".to_string(),
+        }
+    }
+
+    // Data directory of an on-disk engine, `surrealdb.surrealkv_path` or `surrealdb.rocksdb_path`.
+    fn path<'a>(&self, config: &'a SurrealConfig) -> Option<&'a str> {
+        match self {
+            SurrealEngine::Memory => None,
+            SurrealEngine::SurrealKv => Some(&config.surrealkv_path),
+            SurrealEngine::RocksDb => Some(&config.rocksdb_path),
+        }
+    }
+}
+
+// Level of the user we sign in as. Namespace and database users only need access to the
+// configured namespace or database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SurrealAuthLevel {
+    #[default]
+    Root,
+    #[serde(alias = "ns")]
+    Namespace,
+    #[serde(alias = "db")]
+    Database,
+}
+
+// The `[surrealdb]` section of the config file. Without credentials the connection is not
+// signed in.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurrealConfig {
+    pub url: String,
+    pub namespace: String,
+    pub database: String,
+    pub auth_level: SurrealAuthLevel,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub embedded_engines: Vec<SurrealEngine>,
+    pub surrealkv_path: String,
+    pub rocksdb_path: String,
+}
+
+impl Default for SurrealConfig {
+    fn default() -> Self {
+        SurrealConfig {
+            url: String::new(),
+            namespace: String::new(),
+            database: String::new(),
+            auth_level: SurrealAuthLevel::default(),
+            username: None,
+            password: None,
+            embedded_engines: Vec::new(),
+            surrealkv_path: "data/surrealkv".to_string(),
+            rocksdb_path: "data/surrealdb-rocksdb".to_string(),
+        }
+    }
+}
+
+impl SurrealConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.url.is_empty() {
+            errors.push("surrealdb.url is not set".to_string());
+        }
+        if self.namespace.is_empty() {
+            errors.push("surrealdb.namespace is not set".to_string());
+        }
+        if self.database.is_empty() {
+            errors.push("surrealdb.database is not set".to_string());
+        }
+        if self.username.is_some() != self.password.is_some() {
+            errors
+                .push("surrealdb.username and surrealdb.password must be set together".to_string());
+        }
+        for engine in &self.embedded_engines {
+            if !engine.is_available() {
+                errors.push(format!(
+                    "the {} SurrealDB engine needs the surrealdb-rocksdb feature",
+                    engine.as_str()
+                ));
+            }
+            if engine.path(self).is_some_and(str::is_empty) {
+                errors.push(format!("surrealdb.{}_path is not set", engine.as_str()));
+            }
+        }
+    }
+}
+
+// Records are keyed by their start time, e.g. `depth_history:1700000000`.
 pub struct SurrealDBWrapper {
     pub db: Surreal<Any>,
+    // label used in the metrics, e.g. `SurrealDB` or `SurrealDB (mem)`
+    pub name: String,
+    // data directory of an embedded engine
+    path: Option<String>,
 }
 
 impl SurrealDBWrapper {
-    pub async fn init() -> Result<Self> {
-        dotenv().ok();
-
-        let surrealdb_url = match env::var("SURREAL_DATABASE_URL") {
-            Ok(v) => v.to_string(),
-            Err(_) => {
-                return Err(Error::DataBaseConnectionFailed(
-                    "Unable to read SurrealDB URI.".to_string(),
-                ))
-            }
-        };
-
-        let db = any::connect(surrealdb_url).await.map_err(|e| {
+    pub async fn init(config: &SurrealConfig) -> Result<Self> {
+        let db = any::connect(config.url.as_str()).await.map_err(|e| {
             eprintln!("Error connecting to SurrealDB: {:?}", e);
             Error::DataBaseConnectionFailed(
                 "Failed to connect to SurrealDB. Check the database URL.".to_string(),
             )
         })?;
 
-        db.signin(Root {
-            username: "azeem",
-            password: "azeem@4659",
-        })
-        .await
-        .map_err(|e| {
-            eprintln!("Error signing in to SurrealDB as root: {:?}", e);
-            Error::DataBaseConnectionFailed(
-                "Failed to log in as root user to SurrealDB.".to_string(),
-            )
+        Self::signin(&db, config).await?;
+
+        Self::use_database(&db, config).await?;
+
+        let surrealdb = SurrealDBWrapper {
+            db,
+            name: "SurrealDB".to_string(),
+            path: None,
+        };
+
+        Ok(surrealdb)
+    }
+
+    // Embedded engines need no credentials, the caller owns the whole datastore.
+    pub async fn init_embedded(engine: SurrealEngine, config: &SurrealConfig) -> Result<Self> {
+        let db = any::connect(engine.url(config)).await.map_err(|e| {
+            eprintln!(
+                "Error opening embedded SurrealDB ({}): {:?}",
+                engine.as_str(),
+                e
+            );
+            Error::DataBaseConnectionFailed(format!(
+                "Failed to open the embedded {} SurrealDB engine.",
+                engine.as_str()
+            ))
         })?;
 
-        db.use_ns("azeem-0")
-            .use_db("database-metrics-testing")
+        Self::use_database(&db, config).await?;
+
+        Ok(SurrealDBWrapper {
+            db,
+            name: format!("SurrealDB ({})", engine.as_str()),
+            path: engine.path(config).map(str::to_string),
+        })
+    }
+
+    pub async fn init_embedded_engines(config: &SurrealConfig) -> Result<Vec<Self>> {
+        let mut embedded = Vec::new();
+        for engine in &config.embedded_engines {
+            embedded.push(Self::init_embedded(*engine, config).await?);
+        }
+
+        Ok(embedded)
+    }
+
+    async fn signin(db: &Surreal<Any>, config: &SurrealConfig) -> Result<()> {
+        let (username, password) = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => (username.as_str(), password.as_str()),
+            _ => return Ok(()),
+        };
+
+        let signin = match config.auth_level {
+            SurrealAuthLevel::Root => db.signin(Root { username, password }).await,
+            SurrealAuthLevel::Namespace => {
+                db.signin(Namespace {
+                    namespace: &config.namespace,
+                    username,
+                    password,
+                })
+                .await
+            }
+            SurrealAuthLevel::Database => {
+                db.signin(Database {
+                    namespace: &config.namespace,
+                    database: &config.database,
+                    username,
+                    password,
+                })
+                .await
+            }
+        };
+
+        signin.map(|_| ()).map_err(|e| {
+            eprintln!("Error signing in to SurrealDB as {}: {:?}", username, e);
+            Error::DataBaseConnectionFailed(format!(
+                "Failed to log in to SurrealDB as {:?} user {}.",
+                config.auth_level, username
+            ))
+        })
+    }
+
+    async fn use_database(db: &Surreal<Any>, config: &SurrealConfig) -> Result<()> {
+        db.use_ns(config.namespace.as_str())
+            .use_db(config.database.as_str())
             .await
             .map_err(|e| {
                 eprintln!(
@@ -58,15 +243,84 @@ impl SurrealDBWrapper {
                 )
             })?;
 
-        let surrealdb = SurrealDBWrapper { db };
+        Ok(())
+    }
 
-        Ok(surrealdb)
+    // Secondary indexes of the index experiment table, a copy of depth_history.
+    pub async fn create_secondary_indexes(&self) -> Result<()> {
+        self.db
+            .query(
+                "DEFINE INDEX IF NOT EXISTS depth_history_end_time_idx \
+                 ON TABLE depth_history_index_experiment COLUMNS endTime; \
+                 DEFINE INDEX IF NOT EXISTS depth_history_asset_price_usd_idx \
+                 ON TABLE depth_history_index_experiment COLUMNS assetPriceUSD;",
+            )
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                eprintln!("Error defining indexes in SurrealDB: {:?}", e);
+                Error::DataBaseCreationFailed("Failed to define indexes in SurrealDB.".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn drop_secondary_indexes(&self) -> Result<()> {
+        self.db
+            .query(
+                "REMOVE INDEX IF EXISTS depth_history_end_time_idx \
+                 ON TABLE depth_history_index_experiment; \
+                 REMOVE INDEX IF EXISTS depth_history_asset_price_usd_idx \
+                 ON TABLE depth_history_index_experiment;",
+            )
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                eprintln!("Error removing indexes in SurrealDB: {:?}", e);
+                Error::OperationFailed("Failed to remove indexes in SurrealDB.".to_string())
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn insert_index_experiment_record(&self, depth_history: &DepthHistory) -> Result<()> {
+        let _inserted_record: Option<DepthHistory> = self
+            .db
+            .insert(("depth_history_index_experiment", depth_history.start_time))
+            .content(depth_history.clone())
+            .await
+            .map_err(|e| {
+                eprintln!(
+                    "Error inserting index experiment record into SurrealDB: {:?}",
+                    e
+                );
+                Error::DataBaseInsertionFailed(
+                    "Failed to insert index experiment record into SurrealDB.".to_string(),
+                )
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn clear_index_experiment(&self) -> Result<()> {
+        self.db
+            .query("DELETE depth_history_index_experiment;")
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                eprintln!("Error clearing the index experiment in SurrealDB: {:?}", e);
+                Error::OperationFailed(
+                    "Failed to clear the index experiment table in SurrealDB.".to_string(),
+                )
+            })?;
+
+        Ok(())
     }
 
     pub async fn insert_depth_history(&self, depth_history: &DepthHistory) -> Result<()> {
-        let _inserted_record: Vec<DepthHistory> = self
+        let _inserted_record: Option<DepthHistory> = self
             .db
-            .insert("depth_history")
+            .insert(("depth_history", depth_history.start_time))
             .content(depth_history.clone())
             .await
             .map_err(|e| {
@@ -83,9 +337,9 @@ impl SurrealDBWrapper {
         &self,
         rune_pool_history: &RunePoolHistory,
     ) -> Result<()> {
-        let _inserted_record: Vec<RunePoolHistory> = self
+        let _inserted_record: Option<RunePoolHistory> = self
             .db
-            .insert("rune_pool_history")
+            .insert(("rune_pool_history", rune_pool_history.start_time))
             .content(rune_pool_history.clone())
             .await
             .map_err(|e| {
@@ -98,6 +352,149 @@ impl SurrealDBWrapper {
         Ok(())
     }
 
+    // One BEGIN/COMMIT block with a CREATE per record, so the batch is applied as a whole.
+    async fn insert_in_transaction<T: Serialize + Clone + 'static>(
+        &self,
+        table: &str,
+        records: &[(i64, T)],
+    ) -> Result<()> {
+        let mut statements = String::from("BEGIN TRANSACTION;");
+        for i in 0..records.len() {
+            statements.push_str(&format!(
+                " CREATE type::thing('{}', $id_{}) CONTENT $record_{};",
+                table, i, i
+            ));
+        }
+        statements.push_str(" COMMIT TRANSACTION;");
+
+        let mut query = self.db.query(statements);
+        for (i, (id, record)) in records.iter().enumerate() {
+            query = query
+                .bind((format!("id_{}", i), *id))
+                .bind((format!("record_{}", i), record.clone()));
+        }
+
+        query
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                eprintln!(
+                    "Error inserting {} in a SurrealDB transaction: {:?}",
+                    table, e
+                );
+                Error::DataBaseInsertionFailed(format!(
+                    "Failed to insert {} data into SurrealDB in a transaction.",
+                    table
+                ))
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn insert_depth_history_in_transaction(
+        &self,
+        records: &[DepthHistory],
+    ) -> Result<()> {
+        let records: Vec<(i64, DepthHistory)> = records
+            .iter()
+            .map(|record| (record.start_time, record.clone()))
+            .collect();
+
+        self.insert_in_transaction("depth_history", &records).await
+    }
+
+    pub async fn insert_rune_pool_history_in_transaction(
+        &self,
+        records: &[RunePoolHistory],
+    ) -> Result<()> {
+        let records: Vec<(i64, RunePoolHistory)> = records
+            .iter()
+            .map(|record| (record.start_time, record.clone()))
+            .collect();
+
+        self.insert_in_transaction("rune_pool_history", &records)
+            .await
+    }
+
+    pub async fn scale_depth_history_price(&self, start_time: i64, factor: f64) -> Result<()> {
+        self.db
+            .query(
+                "UPDATE depth_history SET assetPriceUSD = assetPriceUSD * $factor \
+                 WHERE startTime = $start_time",
+            )
+            .bind(("factor", factor))
+            .bind(("start_time", start_time))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                eprintln!("Error updating depth history in SurrealDB: {:?}", e);
+                Error::DataBaseInsertionFailed(
+                    "Failed to update depth history data in SurrealDB.".to_string(),
+                )
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn scale_depth_history_prices_from(
+        &self,
+        start_time: i64,
+        factor: f64,
+    ) -> Result<()> {
+        self.db
+            .query(
+                "UPDATE depth_history SET assetPriceUSD = assetPriceUSD * $factor \
+                 WHERE startTime >= $start_time",
+            )
+            .bind(("factor", factor))
+            .bind(("start_time", start_time))
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                eprintln!("Error bulk updating depth history in SurrealDB: {:?}", e);
+                Error::DataBaseInsertionFailed(
+                    "Failed to bulk update depth history data in SurrealDB.".to_string(),
+                )
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn upsert_depth_history(&self, depth_history: &DepthHistory) -> Result<()> {
+        let _upserted_record: Option<DepthHistory> = self
+            .db
+            .upsert(("depth_history", depth_history.start_time))
+            .content(depth_history.clone())
+            .await
+            .map_err(|e| {
+                eprintln!("Error upserting depth history into SurrealDB: {:?}", e);
+                Error::DataBaseInsertionFailed(
+                    "Failed to upsert depth history data into SurrealDB.".to_string(),
+                )
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn upsert_rune_pool_history(
+        &self,
+        rune_pool_history: &RunePoolHistory,
+    ) -> Result<()> {
+        let _upserted_record: Option<RunePoolHistory> = self
+            .db
+            .upsert(("rune_pool_history", rune_pool_history.start_time))
+            .content(rune_pool_history.clone())
+            .await
+            .map_err(|e| {
+                eprintln!("Error upserting rune pool history into SurrealDB: {:?}", e);
+                Error::DataBaseInsertionFailed(
+                    "Failed to upsert rune pool history data into SurrealDB.".to_string(),
+                )
+            })?;
+
+        Ok(())
+    }
+
     pub async fn read_depth_history(&self) -> Result<Vec<DepthHistory>> {
         let depth_history: Vec<DepthHistory> =
             self.db.select("depth_history").await.map_err(|e| {
@@ -113,6 +510,133 @@ impl SurrealDBWrapper {
         Ok(depth_history)
     }
 
+    pub async fn read_depth_history_start_times(&self) -> Result<Vec<i64>> {
+        Ok(self
+            .read_depth_history()
+            .await?
+            .into_iter()
+            .map(|record| record.start_time)
+            .collect())
+    }
+
+    pub async fn read_depth_history_by_start_time(
+        &self,
+        start_time: i64,
+    ) -> Result<Option<DepthHistory>> {
+        let depth_history: Option<DepthHistory> = self
+            .db
+            .select(("depth_history", start_time))
+            .await
+            .map_err(|e| {
+                eprintln!("Error looking up depth history in SurrealDB: {:?}", e);
+                Error::DataBaseReadFailed(
+                    "Failed to look up depth history data in SurrealDB.".to_string(),
+                )
+            })?;
+
+        Ok(depth_history)
+    }
+
+    pub async fn read_index_experiment_by_end_time_range(
+        &self,
+        from_end_time: i64,
+        to_end_time: i64,
+    ) -> Result<Vec<DepthHistory>> {
+        let mut response = self
+            .db
+            .query(
+                "SELECT * FROM depth_history_index_experiment \
+                 WHERE endTime >= $from AND endTime <= $to",
+            )
+            .bind(("from", from_end_time))
+            .bind(("to", to_end_time))
+            .await
+            .map_err(|e| {
+                eprintln!(
+                    "Error querying depth history by end time in SurrealDB: {:?}",
+                    e
+                );
+                Error::DataBaseReadFailed(
+                    "Failed to query depth history by end time in SurrealDB.".to_string(),
+                )
+            })?;
+
+        response.take(0).map_err(|e| {
+            eprintln!("Error decoding depth history from SurrealDB: {:?}", e);
+            Error::DataBaseReadFailed(
+                "Failed to decode depth history data from SurrealDB.".to_string(),
+            )
+        })
+    }
+
+    pub async fn read_index_experiment_by_min_price(
+        &self,
+        min_asset_price_usd: f64,
+    ) -> Result<Vec<DepthHistory>> {
+        let mut response = self
+            .db
+            .query("SELECT * FROM depth_history_index_experiment WHERE assetPriceUSD >= $min_price")
+            .bind(("min_price", min_asset_price_usd))
+            .await
+            .map_err(|e| {
+                eprintln!(
+                    "Error querying depth history by price in SurrealDB: {:?}",
+                    e
+                );
+                Error::DataBaseReadFailed(
+                    "Failed to query depth history by price in SurrealDB.".to_string(),
+                )
+            })?;
+
+        response.take(0).map_err(|e| {
+            eprintln!("Error decoding depth history from SurrealDB: {:?}", e);
+            Error::DataBaseReadFailed(
+                "Failed to decode depth history data from SurrealDB.".to_string(),
+            )
+        })
+    }
+
+    pub async fn aggregate_depth_history(&self) -> Result<DepthHistoryAggregates> {
+        let mut response = self
+            .db
+            .query(
+                "SELECT <int> math::floor(startTime / $seconds_per_day) AS day, \
+                 math::mean(assetPriceUSD) AS avg_asset_price_usd, \
+                 math::max(runeDepth) AS max_rune_depth \
+                 FROM depth_history GROUP BY day ORDER BY day",
+            )
+            .query(
+                "SELECT <int> math::floor(startTime / $seconds_per_week) AS week, \
+                 math::sum(units) AS total_units \
+                 FROM depth_history GROUP BY week ORDER BY week",
+            )
+            .bind(("seconds_per_day", SECONDS_PER_DAY))
+            .bind(("seconds_per_week", SECONDS_PER_WEEK))
+            .await
+            .map_err(|e| {
+                eprintln!("Error aggregating depth history in SurrealDB: {:?}", e);
+                Error::DataBaseReadFailed(
+                    "Failed to aggregate depth history data in SurrealDB.".to_string(),
+                )
+            })?;
+
+        let daily: Vec<DailyDepthAggregate> = response.take(0).map_err(|e| {
+            eprintln!("Error decoding daily aggregates from SurrealDB: {:?}", e);
+            Error::DataBaseReadFailed(
+                "Failed to decode daily depth history aggregates from SurrealDB.".to_string(),
+            )
+        })?;
+
+        let weekly: Vec<WeeklyUnitsAggregate> = response.take(1).map_err(|e| {
+            eprintln!("Error decoding weekly aggregates from SurrealDB: {:?}", e);
+            Error::DataBaseReadFailed(
+                "Failed to decode weekly depth history aggregates from SurrealDB.".to_string(),
+            )
+        })?;
+
+        Ok(DepthHistoryAggregates { daily, weekly })
+    }
+
     pub async fn read_rune_pool_history(&self) -> Result<Vec<RunePoolHistory>> {
         let rune_pool_history: Vec<RunePoolHistory> =
             self.db.select("rune_pool_history").await.map_err(|e| {
@@ -127,4 +651,26 @@ impl SurrealDBWrapper {
 
         Ok(rune_pool_history)
     }
+
+    // SurrealDB does not report storage sizes through its query interface, only the data
+    // directory of an on-disk embedded engine can be measured.
+    pub async fn storage_size(&self) -> Result<Option<u64>> {
+        match &self.path {
+            Some(path) => directory_size(path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn reset(&self) -> Result<()> {
+        self.db
+            .query("DELETE depth_history; DELETE rune_pool_history;")
+            .await
+            .and_then(|response| response.check())
+            .map_err(|e| {
+                eprintln!("Error deleting records in SurrealDB: {:?}", e);
+                Error::OperationFailed("Failed to delete records in SurrealDB.".to_string())
+            })?;
+
+        Ok(())
+    }
 }
@@ -1,2 +1,241 @@
+pub mod aggregate_data;
 pub mod fetch_and_insert_data;
+pub mod index_experiment;
+pub mod point_lookup;
 pub mod read_data;
+pub mod reset;
+pub mod runs;
+pub mod update_data;
+pub mod upsert_data;
+
+use std::{future::Future, time::Duration};
+
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    db::{with_timeout, Backend, DataBases},
+    utils::{
+        run_events::{emit, error, RunEvent},
+        types::{Error, Result},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendStatus {
+    Succeeded,
+    Failed,
+    TimedOut,
+}
+
+// How one operation went on one backend.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendResult {
+    pub backend: String,
+    pub operation: String,
+    pub status: BackendStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// What a workload did: a summary and the result of every backend operation it attempted.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub message: &'static str,
+    pub results: Vec<BackendResult>,
+}
+
+impl Report {
+    pub fn new(message: &'static str) -> Self {
+        Report {
+            message,
+            results: Vec::new(),
+        }
+    }
+
+    // Records how a backend operation went and hands back its value. A failure is logged and
+    // streamed, the caller goes on with the other backends.
+    pub fn record<T>(&mut self, backend: &str, operation: &str, result: Result<T>) -> Option<T> {
+        let (status, value, message) = match result {
+            Ok(value) => (BackendStatus::Succeeded, Some(value), None),
+            Err(err) => {
+                let status = match err {
+                    Error::OperationTimedOut(_) => BackendStatus::TimedOut,
+                    _ => BackendStatus::Failed,
+                };
+                let message = format!("{:?}", err);
+                error(format!(
+                    "{} failed during {}: {}",
+                    backend, operation, message
+                ));
+                (status, None, Some(message))
+            }
+        };
+
+        let result = BackendResult {
+            backend: backend.to_string(),
+            operation: operation.to_string(),
+            status,
+            error: message,
+        };
+        emit(RunEvent::BackendFinished {
+            result: result.clone(),
+        });
+        self.results.push(result);
+
+        value
+    }
+
+    // Runs a backend operation within the operation timeout and records how it went.
+    pub async fn attempt<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        backend: &str,
+        operation: &str,
+        future: impl Future<Output = Result<T>>,
+    ) -> Option<T> {
+        let result = with_timeout(timeout, backend, operation, future).await;
+        self.record(backend, operation, result)
+    }
+
+    // Records a workload that could not start as failed on every backend it was meant for.
+    pub fn not_started(&mut self, backends: &[Backend], operation: &str, message: &str) {
+        for backend in backends {
+            self.record::<()>(
+                backend.name(),
+                operation,
+                Err(Error::OperationFailed(message.to_string())),
+            );
+        }
+    }
+
+    pub fn extend(&mut self, other: Report) {
+        self.results.extend(other.results);
+    }
+
+    pub fn failures(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status != BackendStatus::Succeeded)
+            .count()
+    }
+
+    pub fn summary(&self) -> String {
+        match self.failures() {
+            0 => self.message.to_string(),
+            failures => format!(
+                "{} of {} backend operations failed",
+                failures,
+                self.results.len()
+            ),
+        }
+    }
+
+    // 200 when every backend succeeded, 207 when some failed and 500 when all of them did.
+    pub fn status_code(&self) -> StatusCode {
+        match self.failures() {
+            0 => StatusCode::OK,
+            failures if failures < self.results.len() => StatusCode::MULTI_STATUS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// The start times of the stored depth history, taken from the first selected backend that has
+// any, so the workloads working on them do not depend on one particular backend. Every backend
+// that fails to answer is recorded as failed in the report.
+pub async fn stored_start_times(
+    database: &DataBases,
+    backends: &[Backend],
+    report: &mut Report,
+) -> Vec<i64> {
+    const OPERATION: &str = "reading the stored start times";
+    let timeout = database.operation_timeout;
+
+    for backend in Backend::ALL
+        .iter()
+        .filter(|backend| backends.contains(backend))
+    {
+        let (name, start_times) = match backend {
+            Backend::MongoDB => (
+                "MongoDB",
+                with_timeout(
+                    timeout,
+                    "MongoDB",
+                    OPERATION,
+                    database.mongodb.read_depth_history_start_times(),
+                )
+                .await,
+            ),
+            Backend::Postgres => (
+                "Postgres",
+                with_timeout(
+                    timeout,
+                    "Postgres",
+                    OPERATION,
+                    database.postgres.read_depth_history_start_times(),
+                )
+                .await,
+            ),
+            Backend::SurrealDB => (
+                "SurrealDB",
+                with_timeout(
+                    timeout,
+                    "SurrealDB",
+                    OPERATION,
+                    database.surrealdb.read_depth_history_start_times(),
+                )
+                .await,
+            ),
+            Backend::LevelDB => (
+                "LevelDB",
+                with_timeout(
+                    timeout,
+                    "LevelDB",
+                    OPERATION,
+                    database.leveldb.read_depth_history_start_times(),
+                )
+                .await,
+            ),
+            Backend::RocksDB => (
+                "RocksDB",
+                with_timeout(
+                    timeout,
+                    "RocksDB",
+                    OPERATION,
+                    database.rocksdb.read_depth_history_start_times(),
+                )
+                .await,
+            ),
+        };
+
+        match start_times {
+            Ok(start_times) if !start_times.is_empty() => return start_times,
+            Ok(_) => {}
+            Err(err) => {
+                report.record::<()>(name, OPERATION, Err(err));
+            }
+        }
+    }
+
+    Vec::new()
+}
+
+// Result of a workload, shared by its route and the CLI: the report on success, otherwise the
+// status and message of the failure that stopped it before the backends were attempted.
+pub type Outcome = core::result::Result<Report, (StatusCode, &'static str)>;
+
+pub fn respond(outcome: Outcome) -> Response {
+    match outcome {
+        Ok(report) => (report.status_code(), Json(report)).into_response(),
+        Err((status, message)) => {
+            (status, Json(json!({ "message": message, "results": [] }))).into_response()
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{response::IntoResponse, Extension};
+
+use crate::{
+    db::{
+        leveldb::LevelDB, mongodb::MongoDB, postgres::PostgreSQL, rocksdb::RocksDB,
+        surrealdb::SurrealDBWrapper, Backend, DataBases,
+    },
+    handlers::{respond, Outcome, Report},
+    metrics::performance_metrics::performance_metrics,
+    models::{aggregation_model::DepthHistoryAggregates, depth_history_model::DepthHistory},
+    utils::{
+        kv_keys::DEPTH_HISTORY_PREFIX,
+        types::{Error, Result},
+        write_metrics_into_file::record_metrics,
+    },
+};
+
+async fn aggregate_in_mongodb(mongodb: &MongoDB) -> Result<DepthHistoryAggregates> {
+    let start_time = Instant::now();
+
+    let aggregates = mongodb.aggregate_depth_history().await?;
+
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        "Time taken for MongoDB to aggregate depth history data (aggregation pipeline) : ",
+    );
+
+    Ok(aggregates)
+}
+
+async fn aggregate_in_postgres(postgres: &PostgreSQL) -> Result<DepthHistoryAggregates> {
+    let start_time = Instant::now();
+
+    let aggregates = postgres.aggregate_depth_history().await?;
+
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        "Time taken for Postgres to aggregate depth history data (GROUP BY) : ",
+    );
+
+    Ok(aggregates)
+}
+
+async fn aggregate_in_surrealdb(surrealdb: &SurrealDBWrapper) -> Result<DepthHistoryAggregates> {
+    let start_time = Instant::now();
+
+    let aggregates = surrealdb.aggregate_depth_history().await?;
+
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        "Time taken for SurrealDB to aggregate depth history data (GROUP BY) : ",
+    );
+
+    Ok(aggregates)
+}
+
+fn fold_kv_values(values: Vec<Vec<u8>>, database: &str) -> Result<DepthHistoryAggregates> {
+    let records = values
+        .iter()
+        .map(|val| {
+            serde_json::from_slice::<DepthHistory>(val).map_err(|e| {
+                Error::DataBaseReadFailed(format!(
+                    "Failed to deserialize depth history from {}: {:?}",
+                    database, e
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    DepthHistoryAggregates::fold(&records)
+}
+
+async fn aggregate_in_leveldb(leveldb: &LevelDB) -> Result<DepthHistoryAggregates> {
+    let start_time = Instant::now();
+
+    let values = leveldb.scan_prefix(DEPTH_HISTORY_PREFIX).await?;
+    let aggregates = fold_kv_values(values, "LevelDB")?;
+
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        "Time taken for LevelDB to aggregate depth history data (client-side fold) : ",
+    );
+
+    Ok(aggregates)
+}
+
+async fn aggregate_in_rocksdb(rocksdb: &RocksDB) -> Result<DepthHistoryAggregates> {
+    let start_time = Instant::now();
+
+    let values = rocksdb.scan_prefix(DEPTH_HISTORY_PREFIX).await?;
+    let aggregates = fold_kv_values(values, "RocksDB")?;
+
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        "Time taken for RocksDB to aggregate depth history data (client-side fold) : ",
+    );
+
+    Ok(aggregates)
+}
+
+// Checks every backend's aggregates against the first backend that produced a result, a
+// backend that differs is recorded as a failed comparison.
+fn compare_aggregates(results: &[(&str, DepthHistoryAggregates)], report: &mut Report) {
+    let Some((reference_name, reference)) = results.first() else {
+        return;
+    };
+
+    for (name, aggregates) in &results[1..] {
+        let differences = reference.differences(aggregates);
+
+        if differences.is_empty() {
+            record_metrics(format!(
+                "Aggregation results of {} match {}\n",
+                name, reference_name
+            ));
+        } else {
+            let message = format!(
+                "Aggregation results of {} differ from {}: {}",
+                name,
+                reference_name,
+                differences.join("; ")
+            );
+            record_metrics(format!("{}\n", message));
+            report.record::<()>(
+                name,
+                "aggregate comparison",
+                Err(Error::VerificationFailed(message)),
+            );
+        }
+    }
+}
+
+// Runs the analytical queries in the selected backends and compares their results, used by the
+// route and the `run` command.
+pub async fn aggregate(database: &DataBases, backends: &[Backend]) -> Outcome {
+    let mut report = Report::new("Aggregated data");
+
+    let mut results: Vec<(&str, DepthHistoryAggregates)> = Vec::new();
+
+    if backends.contains(&Backend::Postgres) {
+        if let Some(aggregates) = report
+            .attempt(
+                database.operation_timeout,
+                "Postgres",
+                "aggregate",
+                aggregate_in_postgres(&database.postgres),
+            )
+            .await
+        {
+            results.push(("Postgres", aggregates));
+        }
+    }
+
+    if backends.contains(&Backend::MongoDB) {
+        if let Some(aggregates) = report
+            .attempt(
+                database.operation_timeout,
+                "MongoDB",
+                "aggregate",
+                aggregate_in_mongodb(&database.mongodb),
+            )
+            .await
+        {
+            results.push(("MongoDB", aggregates));
+        }
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        if let Some(aggregates) = report
+            .attempt(
+                database.operation_timeout,
+                "SurrealDB",
+                "aggregate",
+                aggregate_in_surrealdb(&database.surrealdb),
+            )
+            .await
+        {
+            results.push(("SurrealDB", aggregates));
+        }
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        if let Some(aggregates) = report
+            .attempt(
+                database.operation_timeout,
+                "LevelDB",
+                "aggregate",
+                aggregate_in_leveldb(&database.leveldb),
+            )
+            .await
+        {
+            results.push(("LevelDB", aggregates));
+        }
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        if let Some(aggregates) = report
+            .attempt(
+                database.operation_timeout,
+                "RocksDB",
+                "aggregate",
+                aggregate_in_rocksdb(&database.rocksdb),
+            )
+            .await
+        {
+            results.push(("RocksDB", aggregates));
+        }
+    }
+
+    compare_aggregates(&results, &mut report);
+
+    Ok(report)
+}
+
+pub async fn aggregate_data(Extension(database): Extension<Arc<DataBases>>) -> impl IntoResponse {
+    respond(aggregate(&database, &Backend::ALL).await)
+}
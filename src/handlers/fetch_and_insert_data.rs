@@ -1,39 +1,101 @@
 use std::{sync::Arc, time::Instant};
 
 use crate::{
+    config::Config,
     db::{
+        describe_storage_configuration,
         leveldb::{self, LevelDB},
         mongodb::MongoDB,
         postgres::PostgreSQL,
+        reset_databases,
         rocksdb::RocksDB,
         surrealdb::SurrealDBWrapper,
-        DataBases,
+        with_timeout, Backend, DataBases,
     },
-    metrics::performance_metrics::performance_metrics,
+    handlers::{respond, Outcome, Report},
+    metrics::{performance_metrics::performance_metrics, storage_metrics::storage_metrics},
     models::{
-        depth_history_model::DepthHistoryResponse, rune_pool_history_model::RunePoolHistoryResponse,
+        depth_history_model::{DepthHistory, DepthHistoryResponse},
+        rune_pool_history_model::{RunePoolHistory, RunePoolHistoryResponse},
+    },
+    utils::{
+        kv_keys::{
+            depth_history_key, rune_pool_history_key, DEPTH_HISTORY_PREFIX,
+            RUNE_POOL_HISTORY_PREFIX,
+        },
+        midgard_api::Dataset,
+        run_events::{backend_started, error, records_written},
+        types::{Error, Result},
+        verification::{decode_records, verify},
+        write_metrics_into_file::record_metrics,
     },
-    utils::types::{Error, Result},
 };
-use axum::{response::IntoResponse, Extension};
-use reqwest::StatusCode;
+use axum::{extract::Query, response::IntoResponse, Extension};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct FetchAndInsertParams {
+    // empties every database before inserting, so the run starts from a known empty state
+    pub reset: Option<bool>,
+    // wraps each dataset's inserts in a single transaction per backend instead of autocommit
+    pub transactional: Option<bool>,
+}
+
+fn insert_mode(transactional: bool) -> &'static str {
+    if transactional {
+        "transactional"
+    } else {
+        "autocommit"
+    }
+}
 
 async fn insert_depth_history_into_mongodb(
     mongodb: &MongoDB,
     resp: &DepthHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("MongoDB", "insert depth history");
     let start_time = Instant::now();
-    for depth_history in &resp.intervals {
-        if let Err(err) = mongodb.insert_depth_history(depth_history).await {
-            dbg!("Failed to insert depth history data into database");
-            return Err(err);
+
+    if transactional {
+        mongodb
+            .insert_depth_history_in_transaction(&resp.intervals)
+            .await?;
+        records_written(
+            "MongoDB",
+            "depth history",
+            resp.intervals.len(),
+            resp.intervals.len(),
+        );
+    } else {
+        for (written, depth_history) in resp.intervals.iter().enumerate() {
+            if let Err(err) = mongodb.insert_depth_history(depth_history).await {
+                error(format!(
+                    "MongoDB stopped inserting depth history at record {} of {}",
+                    written + 1,
+                    resp.intervals.len()
+                ));
+                return Err(err);
+            }
+            records_written(
+                "MongoDB",
+                "depth history",
+                written + 1,
+                resp.intervals.len(),
+            );
         }
     }
     let end_time = Instant::now();
+
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for MongoDB to insert depth history data (400 records): ",
+        &format!(
+            "Time taken for MongoDB to insert depth history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            mongodb.durability.as_str()
+        ),
     );
 
     Ok(true)
@@ -42,12 +104,37 @@ async fn insert_depth_history_into_mongodb(
 async fn insert_rune_pool_history_into_mongodb(
     mongodb: &MongoDB,
     resp: &RunePoolHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("MongoDB", "insert rune pool history");
     let start_time = Instant::now();
-    for rune_pool_history in &resp.intervals {
-        if let Err(err) = mongodb.insert_rune_pool_history(rune_pool_history).await {
-            dbg!("Failed to insert depth history data into database");
-            return Err(err);
+
+    if transactional {
+        mongodb
+            .insert_rune_pool_history_in_transaction(&resp.intervals)
+            .await?;
+        records_written(
+            "MongoDB",
+            "rune pool history",
+            resp.intervals.len(),
+            resp.intervals.len(),
+        );
+    } else {
+        for (written, rune_pool_history) in resp.intervals.iter().enumerate() {
+            if let Err(err) = mongodb.insert_rune_pool_history(rune_pool_history).await {
+                error(format!(
+                    "MongoDB stopped inserting rune pool history at record {} of {}",
+                    written + 1,
+                    resp.intervals.len()
+                ));
+                return Err(err);
+            }
+            records_written(
+                "MongoDB",
+                "rune pool history",
+                written + 1,
+                resp.intervals.len(),
+            );
         }
     }
     let end_time = Instant::now();
@@ -55,7 +142,12 @@ async fn insert_rune_pool_history_into_mongodb(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for MongoDB to insert rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for MongoDB to insert rune pool history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            mongodb.durability.as_str()
+        ),
     );
 
     Ok(true)
@@ -64,13 +156,37 @@ async fn insert_rune_pool_history_into_mongodb(
 async fn insert_depth_history_into_postgres(
     postgres: &PostgreSQL,
     resp: &DepthHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("Postgres", "insert depth history");
     let start_time = Instant::now();
 
-    for depth_history in &resp.intervals {
-        if let Err(err) = postgres.insert_depth_history(depth_history).await {
-            dbg!("Failed to insert depth history data into database");
-            return Err(err);
+    if transactional {
+        postgres
+            .insert_depth_history_in_transaction(&resp.intervals)
+            .await?;
+        records_written(
+            "Postgres",
+            "depth history",
+            resp.intervals.len(),
+            resp.intervals.len(),
+        );
+    } else {
+        for (written, depth_history) in resp.intervals.iter().enumerate() {
+            if let Err(err) = postgres.insert_depth_history(depth_history).await {
+                error(format!(
+                    "Postgres stopped inserting depth history at record {} of {}",
+                    written + 1,
+                    resp.intervals.len()
+                ));
+                return Err(err);
+            }
+            records_written(
+                "Postgres",
+                "depth history",
+                written + 1,
+                resp.intervals.len(),
+            );
         }
     }
     let end_time = Instant::now();
@@ -78,7 +194,12 @@ async fn insert_depth_history_into_postgres(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for Postgres to insert depth history data (400 records) : ",
+        &format!(
+            "Time taken for Postgres to insert depth history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            postgres.durability.as_str()
+        ),
     );
 
     Ok(true)
@@ -87,13 +208,37 @@ async fn insert_depth_history_into_postgres(
 async fn insert_rune_pool_history_into_postgres(
     postgres: &PostgreSQL,
     resp: &RunePoolHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("Postgres", "insert rune pool history");
     let start_time = Instant::now();
 
-    for rune_pool_history in &resp.intervals {
-        if let Err(err) = postgres.insert_rune_pool_history(rune_pool_history).await {
-            dbg!("Failed to insert depth history data into database");
-            return Err(err);
+    if transactional {
+        postgres
+            .insert_rune_pool_history_in_transaction(&resp.intervals)
+            .await?;
+        records_written(
+            "Postgres",
+            "rune pool history",
+            resp.intervals.len(),
+            resp.intervals.len(),
+        );
+    } else {
+        for (written, rune_pool_history) in resp.intervals.iter().enumerate() {
+            if let Err(err) = postgres.insert_rune_pool_history(rune_pool_history).await {
+                error(format!(
+                    "Postgres stopped inserting rune pool history at record {} of {}",
+                    written + 1,
+                    resp.intervals.len()
+                ));
+                return Err(err);
+            }
+            records_written(
+                "Postgres",
+                "rune pool history",
+                written + 1,
+                resp.intervals.len(),
+            );
         }
     }
     let end_time = Instant::now();
@@ -101,7 +246,12 @@ async fn insert_rune_pool_history_into_postgres(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for Postgres to insert rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for Postgres to insert rune pool history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            postgres.durability.as_str()
+        ),
     );
 
     Ok(true)
@@ -110,13 +260,38 @@ async fn insert_rune_pool_history_into_postgres(
 async fn insert_depth_history_into_surrealdb(
     surrealdb: &SurrealDBWrapper,
     resp: &DepthHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started(&surrealdb.name, "insert depth history");
     let start_time = Instant::now();
 
-    for depth_history in &resp.intervals {
-        if let Err(err) = surrealdb.insert_depth_history(depth_history).await {
-            dbg!("Failed to insert depth history data into surrealDB");
-            return Err(err);
+    if transactional {
+        surrealdb
+            .insert_depth_history_in_transaction(&resp.intervals)
+            .await?;
+        records_written(
+            &surrealdb.name,
+            "depth history",
+            resp.intervals.len(),
+            resp.intervals.len(),
+        );
+    } else {
+        for (written, depth_history) in resp.intervals.iter().enumerate() {
+            if let Err(err) = surrealdb.insert_depth_history(depth_history).await {
+                error(format!(
+                    "{} stopped inserting depth history at record {} of {}",
+                    surrealdb.name,
+                    written + 1,
+                    resp.intervals.len()
+                ));
+                return Err(err);
+            }
+            records_written(
+                &surrealdb.name,
+                "depth history",
+                written + 1,
+                resp.intervals.len(),
+            );
         }
     }
     let end_time = Instant::now();
@@ -124,7 +299,12 @@ async fn insert_depth_history_into_surrealdb(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for SurrealDB to insert depth history data (400 records) : ",
+        &format!(
+            "Time taken for {} to insert depth history data ({} records, {}) : ",
+            surrealdb.name,
+            resp.intervals.len(),
+            insert_mode(transactional)
+        ),
     );
 
     Ok(true)
@@ -133,13 +313,38 @@ async fn insert_depth_history_into_surrealdb(
 async fn insert_rune_pool_history_into_surrealdb(
     surrealdb: &SurrealDBWrapper,
     resp: &RunePoolHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started(&surrealdb.name, "insert rune pool history");
     let start_time = Instant::now();
 
-    for rune_pool_history in &resp.intervals {
-        if let Err(err) = surrealdb.insert_rune_pool_history(rune_pool_history).await {
-            dbg!("Failed to insert Rune pool history data into surrealDB");
-            return Err(err);
+    if transactional {
+        surrealdb
+            .insert_rune_pool_history_in_transaction(&resp.intervals)
+            .await?;
+        records_written(
+            &surrealdb.name,
+            "rune pool history",
+            resp.intervals.len(),
+            resp.intervals.len(),
+        );
+    } else {
+        for (written, rune_pool_history) in resp.intervals.iter().enumerate() {
+            if let Err(err) = surrealdb.insert_rune_pool_history(rune_pool_history).await {
+                error(format!(
+                    "{} stopped inserting rune pool history at record {} of {}",
+                    surrealdb.name,
+                    written + 1,
+                    resp.intervals.len()
+                ));
+                return Err(err);
+            }
+            records_written(
+                &surrealdb.name,
+                "rune pool history",
+                written + 1,
+                resp.intervals.len(),
+            );
         }
     }
     let end_time = Instant::now();
@@ -147,7 +352,12 @@ async fn insert_rune_pool_history_into_surrealdb(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for SurrealDB to insert rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for {} to insert rune pool history data ({} records, {}) : ",
+            surrealdb.name,
+            resp.intervals.len(),
+            insert_mode(transactional)
+        ),
     );
 
     Ok(true)
@@ -156,9 +366,12 @@ async fn insert_rune_pool_history_into_surrealdb(
 async fn insert_depth_history_into_leveldb(
     leveldb: &LevelDB,
     resp: &DepthHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("LevelDB", "insert depth history");
     let start_time = Instant::now();
 
+    let mut entries = Vec::with_capacity(resp.intervals.len());
     for depth_history in &resp.intervals {
         let val = serde_json::to_vec(depth_history).map_err(|e| {
             Error::DataBaseInsertionFailed(format!(
@@ -167,15 +380,24 @@ async fn insert_depth_history_into_leveldb(
             ))
         })?;
 
-        let key = serde_json::to_vec(&depth_history.start_time).map_err(|e| {
-            Error::DataBaseInsertionFailed(format!(
-                "Failed to serialize key for LevelDB (start_time): {:?}",
-                e
-            ))
-        })?;
-        if let Err(err) = leveldb.insert_data(key, val).await {
-            dbg!("Failed to insert depth history data into levelDB");
-            return Err(err);
+        entries.push((depth_history_key(depth_history.start_time), val));
+    }
+
+    let total = entries.len();
+    if transactional {
+        leveldb.insert_data_in_transaction(entries).await?;
+        records_written("LevelDB", "depth history", total, total);
+    } else {
+        for (written, (key, val)) in entries.into_iter().enumerate() {
+            if let Err(err) = leveldb.insert_data(key, val).await {
+                error(format!(
+                    "LevelDB stopped inserting depth history at record {} of {}",
+                    written + 1,
+                    total
+                ));
+                return Err(err);
+            }
+            records_written("LevelDB", "depth history", written + 1, total);
         }
     }
     let end_time = Instant::now();
@@ -183,7 +405,12 @@ async fn insert_depth_history_into_leveldb(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for LevelDB to insert depth history data (400 records) : ",
+        &format!(
+            "Time taken for LevelDB to insert depth history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            leveldb.durability.as_str()
+        ),
     );
 
     Ok(true)
@@ -192,26 +419,38 @@ async fn insert_depth_history_into_leveldb(
 async fn insert_rune_pool_history_into_leveldb(
     leveldb: &LevelDB,
     resp: &RunePoolHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("LevelDB", "insert rune pool history");
     let start_time = Instant::now();
 
+    let mut entries = Vec::with_capacity(resp.intervals.len());
     for rune_pool_history in &resp.intervals {
         let val = serde_json::to_vec(rune_pool_history).map_err(|e| {
             Error::DataBaseInsertionFailed(format!(
-                "Failed to serialize depth history for LevelDB: {:?}",
+                "Failed to serialize rune pool history for LevelDB: {:?}",
                 e
             ))
         })?;
 
-        let key = serde_json::to_vec(&rune_pool_history.start_time).map_err(|e| {
-            Error::DataBaseInsertionFailed(format!(
-                "Failed to serialize key for LevelDB (start_time): {:?}",
-                e
-            ))
-        })?;
-        if let Err(err) = leveldb.insert_data(key, val).await {
-            dbg!("Failed to insert Rune pool history data into surrealDB");
-            return Err(err);
+        entries.push((rune_pool_history_key(rune_pool_history.start_time), val));
+    }
+
+    let total = entries.len();
+    if transactional {
+        leveldb.insert_data_in_transaction(entries).await?;
+        records_written("LevelDB", "rune pool history", total, total);
+    } else {
+        for (written, (key, val)) in entries.into_iter().enumerate() {
+            if let Err(err) = leveldb.insert_data(key, val).await {
+                error(format!(
+                    "LevelDB stopped inserting rune pool history at record {} of {}",
+                    written + 1,
+                    total
+                ));
+                return Err(err);
+            }
+            records_written("LevelDB", "rune pool history", written + 1, total);
         }
     }
     let end_time = Instant::now();
@@ -219,7 +458,12 @@ async fn insert_rune_pool_history_into_leveldb(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for LevelDB to insert rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for LevelDB to insert rune pool history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            leveldb.durability.as_str()
+        ),
     );
 
     Ok(true)
@@ -228,23 +472,39 @@ async fn insert_rune_pool_history_into_leveldb(
 async fn insert_depth_history_into_rocksdb(
     rocksdb: &RocksDB,
     resp: &DepthHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("RocksDB", "insert depth history");
+    rocksdb.use_transactions(transactional).await?;
     let start_time = Instant::now();
 
+    let mut entries = Vec::with_capacity(resp.intervals.len());
     for depth_history in &resp.intervals {
-        let key = serde_json::to_vec(&depth_history.start_time).map_err(|e| {
-            Error::DataBaseConnectionFailed(format!("Failed to serialize key in RocksDB: {:?}", e))
-        })?;
         let val = serde_json::to_vec(depth_history).map_err(|e| {
-            Error::DataBaseConnectionFailed(format!(
-                "Failed to serialize value in RocksDB: {:?}",
+            Error::DataBaseInsertionFailed(format!(
+                "Failed to serialize depth history for RocksDB: {:?}",
                 e
             ))
         })?;
 
-        if let Err(err) = rocksdb.insert_data(key, val).await {
-            dbg!("Failed to insert depth history data into rocksDB");
-            return Err(err);
+        entries.push((depth_history_key(depth_history.start_time), val));
+    }
+
+    let total = entries.len();
+    if transactional {
+        rocksdb.insert_data_in_transaction(entries).await?;
+        records_written("RocksDB", "depth history", total, total);
+    } else {
+        for (written, (key, val)) in entries.into_iter().enumerate() {
+            if let Err(err) = rocksdb.insert_data(key, val).await {
+                error(format!(
+                    "RocksDB stopped inserting depth history at record {} of {}",
+                    written + 1,
+                    total
+                ));
+                return Err(err);
+            }
+            records_written("RocksDB", "depth history", written + 1, total);
         }
     }
     let end_time = Instant::now();
@@ -252,7 +512,12 @@ async fn insert_depth_history_into_rocksdb(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for RocksDB to insert depth history data (400 records) : ",
+        &format!(
+            "Time taken for RocksDB to insert depth history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            rocksdb.durability.as_str()
+        ),
     );
 
     Ok(true)
@@ -261,22 +526,39 @@ async fn insert_depth_history_into_rocksdb(
 async fn insert_rune_pool_history_into_rocksdb(
     rocksdb: &RocksDB,
     resp: &RunePoolHistoryResponse,
+    transactional: bool,
 ) -> Result<bool> {
+    backend_started("RocksDB", "insert rune pool history");
+    rocksdb.use_transactions(transactional).await?;
     let start_time = Instant::now();
 
+    let mut entries = Vec::with_capacity(resp.intervals.len());
     for rune_pool_history in &resp.intervals {
-        let key = serde_json::to_vec(&rune_pool_history.start_time).map_err(|e| {
-            Error::DataBaseConnectionFailed(format!("Failed to serialize key in RocksDB: {:?}", e))
-        })?;
         let val = serde_json::to_vec(rune_pool_history).map_err(|e| {
-            Error::DataBaseConnectionFailed(format!(
-                "Failed to serialize value in RocksDB: {:?}",
+            Error::DataBaseInsertionFailed(format!(
+                "Failed to serialize rune pool history for RocksDB: {:?}",
                 e
             ))
         })?;
-        if let Err(err) = rocksdb.insert_data(key, val).await {
-            dbg!("Failed to insert Rune pool history data into surrealDB");
-            return Err(err);
+
+        entries.push((rune_pool_history_key(rune_pool_history.start_time), val));
+    }
+
+    let total = entries.len();
+    if transactional {
+        rocksdb.insert_data_in_transaction(entries).await?;
+        records_written("RocksDB", "rune pool history", total, total);
+    } else {
+        for (written, (key, val)) in entries.into_iter().enumerate() {
+            if let Err(err) = rocksdb.insert_data(key, val).await {
+                error(format!(
+                    "RocksDB stopped inserting rune pool history at record {} of {}",
+                    written + 1,
+                    total
+                ));
+                return Err(err);
+            }
+            records_written("RocksDB", "rune pool history", written + 1, total);
         }
     }
     let end_time = Instant::now();
@@ -284,141 +566,480 @@ async fn insert_rune_pool_history_into_rocksdb(
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for LevelDB to insert rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for RocksDB to insert rune pool history data ({} records, {}, durability {}) : ",
+            resp.intervals.len(),
+            insert_mode(transactional),
+            rocksdb.durability.as_str()
+        ),
     );
 
     Ok(true)
 }
 
-async fn insert_depth_history(
-    mongodb: &MongoDB,
-    postgres: &PostgreSQL,
-    surrealdb: &SurrealDBWrapper,
-    leveldb: &LevelDB,
-    rocksdb: &RocksDB,
-) -> Result<bool> {
-    let depth_history_url =
-        "https://midgard.ninerealms.com/v2/history/depths/BTC.BTC?interval=hour&count=400";
-
-    match reqwest::get(depth_history_url).await {
-        Ok(response) => match response.json::<DepthHistoryResponse>().await {
-            Ok(resp) => {
-                if let Err(err) = insert_depth_history_into_mongodb(mongodb, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_depth_history_into_postgres(postgres, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_depth_history_into_surrealdb(&surrealdb, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_depth_history_into_leveldb(leveldb, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_depth_history_into_rocksdb(rocksdb, &resp).await {
-                    return Err(err);
-                }
-            }
-            Err(err) => {
-                dbg!(err);
-                return Err(Error::OperationFailed(
-                    "Cannot fetch data from api.".to_string(),
-                ));
-            }
-        },
-        Err(err) => {
-            dbg!(err);
-            return Err(Error::OperationFailed(
-                "Cannot fetch data from api.".to_string(),
+// A backend whose size cannot be read is reported as not available, the other backends are
+// still measured.
+async fn report_storage_footprint(
+    database: &DataBases,
+    backends: &[Backend],
+    records: usize,
+    phase: &str,
+) {
+    let timeout = database.operation_timeout;
+    let mut sizes = Vec::new();
+    if backends.contains(&Backend::MongoDB) {
+        sizes.push((
+            "MongoDB",
+            with_timeout(
+                timeout,
+                "MongoDB",
+                "storage size",
+                database.mongodb.storage_size(),
+            )
+            .await
+            .map(Some),
+        ));
+    }
+    if backends.contains(&Backend::Postgres) {
+        sizes.push((
+            "Postgres",
+            with_timeout(
+                timeout,
+                "Postgres",
+                "storage size",
+                database.postgres.storage_size(),
+            )
+            .await
+            .map(Some),
+        ));
+    }
+    if backends.contains(&Backend::SurrealDB) {
+        sizes.push((
+            "SurrealDB",
+            with_timeout(
+                timeout,
+                "SurrealDB",
+                "storage size",
+                database.surrealdb.storage_size(),
+            )
+            .await,
+        ));
+        for surrealdb in &database.surrealdb_embedded {
+            sizes.push((
+                surrealdb.name.as_str(),
+                with_timeout(
+                    timeout,
+                    &surrealdb.name,
+                    "storage size",
+                    surrealdb.storage_size(),
+                )
+                .await,
             ));
         }
     }
+    if backends.contains(&Backend::LevelDB) {
+        sizes.push((
+            "LevelDB",
+            with_timeout(
+                timeout,
+                "LevelDB",
+                "storage size",
+                database.leveldb.storage_size(),
+            )
+            .await
+            .map(Some),
+        ));
+    }
+    if backends.contains(&Backend::RocksDB) {
+        sizes.push((
+            "RocksDB",
+            with_timeout(
+                timeout,
+                "RocksDB",
+                "storage size",
+                database.rocksdb.storage_size(),
+            )
+            .await
+            .map(Some),
+        ));
+    }
 
-    Ok(true)
+    for (name, size) in sizes {
+        let bytes = size.unwrap_or_else(|err| {
+            error(format!(
+                "Failed to read the storage size of {}: {:?}",
+                name, err
+            ));
+            None
+        });
+
+        storage_metrics(
+            bytes,
+            records,
+            &format!("Storage used by {} after {} :", name, phase),
+        );
+    }
+}
+
+// Reads the depth history back from every backend and compares it with the fetched records, so
+// a backend that drops or alters records fails instead of only being fast.
+async fn verify_depth_history(
+    database: &DataBases,
+    backends: &[Backend],
+    source: &[DepthHistory],
+    report: &mut Report,
+) {
+    let timeout = database.operation_timeout;
+    let operation = "verify depth history";
+
+    if backends.contains(&Backend::MongoDB) {
+        report
+            .attempt(timeout, "MongoDB", operation, async {
+                let stored = database.mongodb.read_depth_history().await?;
+                verify("MongoDB", "depth history", source, &stored)
+            })
+            .await;
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        report
+            .attempt(timeout, "Postgres", operation, async {
+                let stored = database.postgres.read_depth_history().await?;
+                verify("Postgres", "depth history", source, &stored)
+            })
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        for surrealdb in std::iter::once(&database.surrealdb).chain(&database.surrealdb_embedded) {
+            report
+                .attempt(timeout, &surrealdb.name, operation, async {
+                    let stored = surrealdb.read_depth_history().await?;
+                    verify(&surrealdb.name, "depth history", source, &stored)
+                })
+                .await;
+        }
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(timeout, "LevelDB", operation, async {
+                let values = database.leveldb.scan_prefix(DEPTH_HISTORY_PREFIX).await?;
+                let stored: Vec<DepthHistory> = decode_records(values, "LevelDB")?;
+                verify("LevelDB", "depth history", source, &stored)
+            })
+            .await;
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(timeout, "RocksDB", operation, async {
+                let values = database.rocksdb.scan_prefix(DEPTH_HISTORY_PREFIX).await?;
+                let stored: Vec<DepthHistory> = decode_records(values, "RocksDB")?;
+                verify("RocksDB", "depth history", source, &stored)
+            })
+            .await;
+    }
+}
+
+async fn verify_rune_pool_history(
+    database: &DataBases,
+    backends: &[Backend],
+    source: &[RunePoolHistory],
+    report: &mut Report,
+) {
+    let timeout = database.operation_timeout;
+    let operation = "verify rune pool history";
+
+    if backends.contains(&Backend::MongoDB) {
+        report
+            .attempt(timeout, "MongoDB", operation, async {
+                let stored = database.mongodb.read_rune_pool_history().await?;
+                verify("MongoDB", "rune pool history", source, &stored)
+            })
+            .await;
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        report
+            .attempt(timeout, "Postgres", operation, async {
+                let stored = database.postgres.read_rune_pool_history().await?;
+                verify("Postgres", "rune pool history", source, &stored)
+            })
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        for surrealdb in std::iter::once(&database.surrealdb).chain(&database.surrealdb_embedded) {
+            report
+                .attempt(timeout, &surrealdb.name, operation, async {
+                    let stored = surrealdb.read_rune_pool_history().await?;
+                    verify(&surrealdb.name, "rune pool history", source, &stored)
+                })
+                .await;
+        }
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(timeout, "LevelDB", operation, async {
+                let values = database
+                    .leveldb
+                    .scan_prefix(RUNE_POOL_HISTORY_PREFIX)
+                    .await?;
+                let stored: Vec<RunePoolHistory> = decode_records(values, "LevelDB")?;
+                verify("LevelDB", "rune pool history", source, &stored)
+            })
+            .await;
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(timeout, "RocksDB", operation, async {
+                let values = database
+                    .rocksdb
+                    .scan_prefix(RUNE_POOL_HISTORY_PREFIX)
+                    .await?;
+                let stored: Vec<RunePoolHistory> = decode_records(values, "RocksDB")?;
+                verify("RocksDB", "rune pool history", source, &stored)
+            })
+            .await;
+    }
+}
+
+async fn insert_depth_history(
+    database: &DataBases,
+    backends: &[Backend],
+    url: &str,
+    transactional: bool,
+    report: &mut Report,
+) -> Result<usize> {
+    let resp = database.midgard.fetch_depth_history(url).await?;
+
+    if backends.contains(&Backend::MongoDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "MongoDB",
+                "insert depth history",
+                insert_depth_history_into_mongodb(&database.mongodb, &resp, transactional),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "Postgres",
+                "insert depth history",
+                insert_depth_history_into_postgres(&database.postgres, &resp, transactional),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "SurrealDB",
+                "insert depth history",
+                insert_depth_history_into_surrealdb(&database.surrealdb, &resp, transactional),
+            )
+            .await;
+
+        for surrealdb in &database.surrealdb_embedded {
+            report
+                .attempt(
+                    database.operation_timeout,
+                    &surrealdb.name,
+                    "insert depth history",
+                    insert_depth_history_into_surrealdb(surrealdb, &resp, transactional),
+                )
+                .await;
+        }
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "LevelDB",
+                "insert depth history",
+                insert_depth_history_into_leveldb(&database.leveldb, &resp, transactional),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "RocksDB",
+                "insert depth history",
+                insert_depth_history_into_rocksdb(&database.rocksdb, &resp, transactional),
+            )
+            .await;
+    }
+
+    verify_depth_history(database, backends, &resp.intervals, report).await;
+
+    Ok(resp.intervals.len())
 }
 
 async fn insert_rune_pool_history(
-    mongodb: &MongoDB,
-    postgres: &PostgreSQL,
-    surrealdb: &SurrealDBWrapper,
-    leveldb: &LevelDB,
-    rocksdb: &RocksDB,
-) -> Result<bool> {
-    let rune_pool_history_url =
-        format!("https://midgard.ninerealms.com/v2/history/runepool?interval=hour&count=400");
-
-    match reqwest::get(&rune_pool_history_url).await {
-        Ok(response) => match response.json::<RunePoolHistoryResponse>().await {
-            Ok(resp) => {
-                if let Err(err) = insert_rune_pool_history_into_mongodb(mongodb, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_rune_pool_history_into_postgres(postgres, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_rune_pool_history_into_surrealdb(&surrealdb, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_rune_pool_history_into_leveldb(&leveldb, &resp).await {
-                    return Err(err);
-                }
-
-                if let Err(err) = insert_rune_pool_history_into_rocksdb(&rocksdb, &resp).await {
-                    return Err(err);
-                }
-            }
-            Err(err) => {
-                dbg!(err);
-                return Err(Error::OperationFailed(
-                    "Cannot fetch data from api.".to_string(),
-                ));
-            }
-        },
-        Err(err) => {
-            dbg!(err);
-            return Err(Error::OperationFailed(
-                "Cannot fetch data from api.".to_string(),
-            ));
+    database: &DataBases,
+    backends: &[Backend],
+    url: &str,
+    transactional: bool,
+    report: &mut Report,
+) -> Result<usize> {
+    let resp = database.midgard.fetch_rune_pool_history(url).await?;
+
+    if backends.contains(&Backend::MongoDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "MongoDB",
+                "insert rune pool history",
+                insert_rune_pool_history_into_mongodb(&database.mongodb, &resp, transactional),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "Postgres",
+                "insert rune pool history",
+                insert_rune_pool_history_into_postgres(&database.postgres, &resp, transactional),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "SurrealDB",
+                "insert rune pool history",
+                insert_rune_pool_history_into_surrealdb(&database.surrealdb, &resp, transactional),
+            )
+            .await;
+
+        for surrealdb in &database.surrealdb_embedded {
+            report
+                .attempt(
+                    database.operation_timeout,
+                    &surrealdb.name,
+                    "insert rune pool history",
+                    insert_rune_pool_history_into_surrealdb(surrealdb, &resp, transactional),
+                )
+                .await;
         }
     }
 
-    Ok(true)
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "LevelDB",
+                "insert rune pool history",
+                insert_rune_pool_history_into_leveldb(&database.leveldb, &resp, transactional),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "RocksDB",
+                "insert rune pool history",
+                insert_rune_pool_history_into_rocksdb(&database.rocksdb, &resp, transactional),
+            )
+            .await;
+    }
+
+    verify_rune_pool_history(database, backends, &resp.intervals, report).await;
+
+    Ok(resp.intervals.len())
 }
 
-pub async fn fetch_and_insert_data(
-    Extension(database): Extension<Arc<DataBases>>,
-) -> impl IntoResponse {
-    let mongodb = &database.mongodb;
-    let postgres = &database.postgres;
-    let surrealdb = &database.surrealdb;
-    let leveldb = &database.leveldb;
-    let rocksdb = &database.rocksdb;
-
-    if let Err(err) = insert_depth_history(mongodb, postgres, surrealdb, leveldb, rocksdb).await {
-        dbg!(err);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to insert depth history data",
+// Fetches the selected datasets and inserts them into the selected backends, used by the route
+// and the `ingest` command.
+pub async fn ingest(
+    database: &DataBases,
+    config: &Config,
+    backends: &[Backend],
+    datasets: &[Dataset],
+    reset: bool,
+    transactional: bool,
+) -> Outcome {
+    let mut report = Report::new("Inserted data");
+
+    if reset {
+        for (backend, result) in reset_databases(database, backends).await {
+            report.record(&backend, "reset", result);
+        }
+    }
+
+    record_metrics(describe_storage_configuration(database));
+
+    // records inserted by this run, bytes per record is only meaningful after reset=true
+    let mut records = 0;
+
+    if datasets.contains(&Dataset::DepthHistory) {
+        let inserted = insert_depth_history(
+            database,
+            backends,
+            &config.datasets.depth_history_url,
+            transactional,
+            &mut report,
         )
-            .into_response();
+        .await;
+
+        // a dataset that could not be fetched fails on its own, the other one is still ingested
+        if let Some(inserted) = report.record("Midgard", "fetch depth history", inserted) {
+            records += inserted;
+            report_storage_footprint(database, backends, records, "depth history insert").await;
+        }
     }
 
-    if let Err(err) = insert_rune_pool_history(mongodb, postgres, surrealdb, leveldb, rocksdb).await
-    {
-        dbg!(err);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to insert rune pool history data",
+    if datasets.contains(&Dataset::RunePoolHistory) {
+        let inserted = insert_rune_pool_history(
+            database,
+            backends,
+            &config.datasets.rune_pool_history_url,
+            transactional,
+            &mut report,
         )
-            .into_response();
+        .await;
+
+        if let Some(inserted) = report.record("Midgard", "fetch rune pool history", inserted) {
+            records += inserted;
+            report_storage_footprint(database, backends, records, "rune pool history insert").await;
+        }
     }
 
-    (StatusCode::OK, "Inserted data").into_response()
+    Ok(report)
+}
+
+pub async fn fetch_and_insert_data(
+    Extension(database): Extension<Arc<DataBases>>,
+    Extension(config): Extension<Arc<Config>>,
+    Query(params): Query<FetchAndInsertParams>,
+) -> impl IntoResponse {
+    respond(
+        ingest(
+            &database,
+            &config,
+            &Backend::ALL,
+            &Dataset::ALL,
+            params.reset.unwrap_or(false),
+            params.transactional.unwrap_or(false),
+        )
+        .await,
+    )
 }
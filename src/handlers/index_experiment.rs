@@ -0,0 +1,570 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{response::IntoResponse, Extension};
+use reqwest::StatusCode;
+
+use crate::{
+    config::Config,
+    db::{
+        leveldb::LevelDB,
+        mongodb::MongoDB,
+        postgres::{PostgreSQL, PostgresIndexType},
+        rocksdb::RocksDB,
+        surrealdb::SurrealDBWrapper,
+        Backend, DataBases,
+    },
+    handlers::{respond, Outcome, Report},
+    metrics::performance_metrics::performance_metrics,
+    models::depth_history_model::{DepthHistory, DepthHistoryResponse},
+    utils::{
+        kv_keys::{
+            depth_history_end_time_index_key, depth_history_end_time_index_range,
+            depth_history_price_index_key, depth_history_price_index_range, index_experiment_key,
+            INDEX_EXPERIMENT_PREFIX, INDEX_EXPERIMENT_RECORD_PREFIX,
+        },
+        run_events::error,
+        types::{Error, Result},
+    },
+};
+
+// The end time query selects the middle tenth of the intervals and the price query the most
+// expensive tenth, so both are selective enough for an index to matter.
+struct QueryBounds {
+    from_end_time: i64,
+    to_end_time: i64,
+    min_asset_price_usd: f64,
+}
+
+impl QueryBounds {
+    fn from_records(records: &[DepthHistory]) -> Result<Self> {
+        if records.is_empty() {
+            return Err(Error::OperationFailed(
+                "No depth history records to run the index experiment with.".to_string(),
+            ));
+        }
+
+        let mut end_times: Vec<i64> = records.iter().map(|record| record.end_time).collect();
+        end_times.sort();
+
+        let mut prices: Vec<f64> = records
+            .iter()
+            .map(|record| record.asset_price_usd)
+            .collect();
+        prices.sort_by(|a, b| a.total_cmp(b));
+
+        let len = records.len();
+
+        Ok(QueryBounds {
+            from_end_time: end_times[len * 45 / 100],
+            to_end_time: end_times[(len * 55 / 100).min(len - 1)],
+            min_asset_price_usd: prices[len * 90 / 100],
+        })
+    }
+}
+
+fn report(start_time: Instant, database: &str, action: &str, variant: &str, count: usize) {
+    performance_metrics(
+        start_time,
+        Instant::now(),
+        &format!(
+            "Time taken for {} to {} {} ({} records) : ",
+            database, action, variant, count
+        ),
+    );
+}
+
+async fn index_experiment_postgres(
+    postgres: &PostgreSQL,
+    resp: &DepthHistoryResponse,
+    bounds: &QueryBounds,
+) -> Result<bool> {
+    for (index_type, variant) in [
+        (None, "without secondary indexes"),
+        (Some(PostgresIndexType::BTree), "with B-tree indexes"),
+        (Some(PostgresIndexType::Brin), "with BRIN indexes"),
+    ] {
+        postgres.drop_secondary_indexes().await?;
+        postgres.clear_index_experiment().await?;
+
+        if let Some(index_type) = index_type {
+            postgres.create_secondary_indexes(index_type).await?;
+        }
+
+        let start_time = Instant::now();
+        for depth_history in &resp.intervals {
+            postgres
+                .insert_index_experiment_record(depth_history)
+                .await?;
+        }
+        report(
+            start_time,
+            "Postgres",
+            "insert depth history data",
+            variant,
+            resp.intervals.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = postgres
+            .read_index_experiment_by_end_time_range(bounds.from_end_time, bounds.to_end_time)
+            .await?;
+        report(
+            start_time,
+            "Postgres",
+            "query depth history by end_time range",
+            variant,
+            records.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = postgres
+            .read_index_experiment_by_min_price(bounds.min_asset_price_usd)
+            .await?;
+        report(
+            start_time,
+            "Postgres",
+            "query depth history by asset_price_usd",
+            variant,
+            records.len(),
+        );
+    }
+
+    postgres.drop_secondary_indexes().await?;
+    postgres.clear_index_experiment().await?;
+
+    Ok(true)
+}
+
+async fn index_experiment_mongodb(
+    mongodb: &MongoDB,
+    resp: &DepthHistoryResponse,
+    bounds: &QueryBounds,
+) -> Result<bool> {
+    for (indexed, variant) in [
+        (false, "without secondary indexes"),
+        (true, "with single-field indexes"),
+    ] {
+        mongodb.clear_index_experiment().await?;
+
+        if indexed {
+            mongodb.create_secondary_indexes().await?;
+        }
+
+        let start_time = Instant::now();
+        for depth_history in &resp.intervals {
+            mongodb
+                .insert_index_experiment_record(depth_history)
+                .await?;
+        }
+        report(
+            start_time,
+            "MongoDB",
+            "insert depth history data",
+            variant,
+            resp.intervals.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = mongodb
+            .find_index_experiment_by_end_time_range(bounds.from_end_time, bounds.to_end_time)
+            .await?;
+        report(
+            start_time,
+            "MongoDB",
+            "query depth history by end_time range",
+            variant,
+            records.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = mongodb
+            .find_index_experiment_by_min_price(bounds.min_asset_price_usd)
+            .await?;
+        report(
+            start_time,
+            "MongoDB",
+            "query depth history by asset_price_usd",
+            variant,
+            records.len(),
+        );
+    }
+
+    mongodb.clear_index_experiment().await?;
+
+    Ok(true)
+}
+
+async fn index_experiment_surrealdb(
+    surrealdb: &SurrealDBWrapper,
+    resp: &DepthHistoryResponse,
+    bounds: &QueryBounds,
+) -> Result<bool> {
+    for (indexed, variant) in [
+        (false, "without secondary indexes"),
+        (true, "with DEFINE INDEX indexes"),
+    ] {
+        surrealdb.drop_secondary_indexes().await?;
+        surrealdb.clear_index_experiment().await?;
+
+        if indexed {
+            surrealdb.create_secondary_indexes().await?;
+        }
+
+        let start_time = Instant::now();
+        for depth_history in &resp.intervals {
+            surrealdb
+                .insert_index_experiment_record(depth_history)
+                .await?;
+        }
+        report(
+            start_time,
+            "SurrealDB",
+            "insert depth history data",
+            variant,
+            resp.intervals.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = surrealdb
+            .read_index_experiment_by_end_time_range(bounds.from_end_time, bounds.to_end_time)
+            .await?;
+        report(
+            start_time,
+            "SurrealDB",
+            "query depth history by end_time range",
+            variant,
+            records.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = surrealdb
+            .read_index_experiment_by_min_price(bounds.min_asset_price_usd)
+            .await?;
+        report(
+            start_time,
+            "SurrealDB",
+            "query depth history by asset_price_usd",
+            variant,
+            records.len(),
+        );
+    }
+
+    surrealdb.drop_secondary_indexes().await?;
+    surrealdb.clear_index_experiment().await?;
+
+    Ok(true)
+}
+
+fn serialize_depth_history(depth_history: &DepthHistory, database: &str) -> Result<Vec<u8>> {
+    serde_json::to_vec(depth_history).map_err(|e| {
+        Error::DataBaseInsertionFailed(format!(
+            "Failed to serialize depth history for {}: {:?}",
+            database, e
+        ))
+    })
+}
+
+fn deserialize_depth_history(values: &[Vec<u8>], database: &str) -> Result<Vec<DepthHistory>> {
+    values
+        .iter()
+        .map(|val| {
+            serde_json::from_slice::<DepthHistory>(val).map_err(|e| {
+                Error::DataBaseReadFailed(format!(
+                    "Failed to deserialize depth history from {}: {:?}",
+                    database, e
+                ))
+            })
+        })
+        .collect()
+}
+
+// The record and, when indexed, its index entries, each entry pointing at the record key.
+fn index_experiment_entries(
+    depth_history: &DepthHistory,
+    indexed: bool,
+    database: &str,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let key = index_experiment_key(depth_history.start_time);
+    let mut entries = vec![(
+        key.clone(),
+        serialize_depth_history(depth_history, database)?,
+    )];
+
+    if indexed {
+        entries.push((
+            depth_history_end_time_index_key(depth_history.end_time, depth_history.start_time),
+            key.clone(),
+        ));
+        entries.push((
+            depth_history_price_index_key(depth_history.asset_price_usd, depth_history.start_time),
+            key,
+        ));
+    }
+
+    Ok(entries)
+}
+
+// Without an index the key-value stores scan every depth history record and filter in Rust,
+// with the manual index they scan the index key range and fetch the referenced records.
+async fn index_experiment_leveldb(
+    leveldb: &LevelDB,
+    resp: &DepthHistoryResponse,
+    bounds: &QueryBounds,
+) -> Result<bool> {
+    for (indexed, variant) in [
+        (false, "without secondary indexes"),
+        (true, "with manual index keys"),
+    ] {
+        leveldb.delete_prefix(INDEX_EXPERIMENT_PREFIX).await?;
+
+        // Every record is written with its index entries in one batch, so both variants rewrite
+        // the keys index once per record and only the index entries add to the insert time.
+        let start_time = Instant::now();
+        for depth_history in &resp.intervals {
+            leveldb
+                .insert_data_in_transaction(index_experiment_entries(
+                    depth_history,
+                    indexed,
+                    "LevelDB",
+                )?)
+                .await?;
+        }
+        report(
+            start_time,
+            "LevelDB",
+            "insert depth history data",
+            variant,
+            resp.intervals.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = if indexed {
+            let (from, to) =
+                depth_history_end_time_index_range(bounds.from_end_time, bounds.to_end_time);
+            let mut values = Vec::new();
+            for key in leveldb.scan_range(&from, &to).await? {
+                values.extend(leveldb.get_data(key).await?);
+            }
+            deserialize_depth_history(&values, "LevelDB")?
+        } else {
+            let values = leveldb.scan_prefix(INDEX_EXPERIMENT_RECORD_PREFIX).await?;
+            deserialize_depth_history(&values, "LevelDB")?
+                .into_iter()
+                .filter(|record| {
+                    record.end_time >= bounds.from_end_time && record.end_time <= bounds.to_end_time
+                })
+                .collect()
+        };
+        report(
+            start_time,
+            "LevelDB",
+            "query depth history by end_time range",
+            variant,
+            records.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = if indexed {
+            let (from, to) = depth_history_price_index_range(bounds.min_asset_price_usd);
+            let mut values = Vec::new();
+            for key in leveldb.scan_range(&from, &to).await? {
+                values.extend(leveldb.get_data(key).await?);
+            }
+            deserialize_depth_history(&values, "LevelDB")?
+        } else {
+            let values = leveldb.scan_prefix(INDEX_EXPERIMENT_RECORD_PREFIX).await?;
+            deserialize_depth_history(&values, "LevelDB")?
+                .into_iter()
+                .filter(|record| record.asset_price_usd >= bounds.min_asset_price_usd)
+                .collect()
+        };
+        report(
+            start_time,
+            "LevelDB",
+            "query depth history by asset_price_usd",
+            variant,
+            records.len(),
+        );
+    }
+
+    leveldb.delete_prefix(INDEX_EXPERIMENT_PREFIX).await?;
+
+    Ok(true)
+}
+
+async fn index_experiment_rocksdb(
+    rocksdb: &RocksDB,
+    resp: &DepthHistoryResponse,
+    bounds: &QueryBounds,
+) -> Result<bool> {
+    for (indexed, variant) in [
+        (false, "without secondary indexes"),
+        (true, "with manual index keys"),
+    ] {
+        rocksdb.delete_prefix(INDEX_EXPERIMENT_PREFIX).await?;
+
+        let start_time = Instant::now();
+        for depth_history in &resp.intervals {
+            for (key, val) in index_experiment_entries(depth_history, indexed, "RocksDB")? {
+                rocksdb.insert_data(key, val).await?;
+            }
+        }
+        report(
+            start_time,
+            "RocksDB",
+            "insert depth history data",
+            variant,
+            resp.intervals.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = if indexed {
+            let (from, to) =
+                depth_history_end_time_index_range(bounds.from_end_time, bounds.to_end_time);
+            let mut values = Vec::new();
+            for key in rocksdb.scan_range(&from, &to).await? {
+                values.extend(rocksdb.get_data(key).await?);
+            }
+            deserialize_depth_history(&values, "RocksDB")?
+        } else {
+            let values = rocksdb.scan_prefix(INDEX_EXPERIMENT_RECORD_PREFIX).await?;
+            deserialize_depth_history(&values, "RocksDB")?
+                .into_iter()
+                .filter(|record| {
+                    record.end_time >= bounds.from_end_time && record.end_time <= bounds.to_end_time
+                })
+                .collect()
+        };
+        report(
+            start_time,
+            "RocksDB",
+            "query depth history by end_time range",
+            variant,
+            records.len(),
+        );
+
+        let start_time = Instant::now();
+        let records = if indexed {
+            let (from, to) = depth_history_price_index_range(bounds.min_asset_price_usd);
+            let mut values = Vec::new();
+            for key in rocksdb.scan_range(&from, &to).await? {
+                values.extend(rocksdb.get_data(key).await?);
+            }
+            deserialize_depth_history(&values, "RocksDB")?
+        } else {
+            let values = rocksdb.scan_prefix(INDEX_EXPERIMENT_RECORD_PREFIX).await?;
+            deserialize_depth_history(&values, "RocksDB")?
+                .into_iter()
+                .filter(|record| record.asset_price_usd >= bounds.min_asset_price_usd)
+                .collect()
+        };
+        report(
+            start_time,
+            "RocksDB",
+            "query depth history by asset_price_usd",
+            variant,
+            records.len(),
+        );
+    }
+
+    rocksdb.delete_prefix(INDEX_EXPERIMENT_PREFIX).await?;
+
+    Ok(true)
+}
+
+// Measures secondary indexes on depth history in the selected backends, used by the route and
+// the `run` command. Each backend works on its own experiment table, collection or key prefix,
+// which is cleared afterwards, so the ingested benchmark data is left as it was.
+pub async fn experiment(database: &DataBases, config: &Config, backends: &[Backend]) -> Outcome {
+    let mut report = Report::new("Completed index experiment");
+
+    let resp = match database
+        .midgard
+        .fetch_depth_history(&config.datasets.depth_history_url)
+        .await
+    {
+        Ok(resp) => resp,
+        Err(err) => {
+            error(format!("Failed to fetch depth history data: {:?}", err));
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to fetch depth history data",
+            ));
+        }
+    };
+
+    let bounds = match QueryBounds::from_records(&resp.intervals) {
+        Ok(bounds) => bounds,
+        Err(err) => {
+            error(format!("Index experiment aborted: {:?}", err));
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "No depth history data to experiment with",
+            ));
+        }
+    };
+
+    if backends.contains(&Backend::MongoDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "MongoDB",
+                "index experiment",
+                index_experiment_mongodb(&database.mongodb, &resp, &bounds),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "Postgres",
+                "index experiment",
+                index_experiment_postgres(&database.postgres, &resp, &bounds),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "SurrealDB",
+                "index experiment",
+                index_experiment_surrealdb(&database.surrealdb, &resp, &bounds),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "LevelDB",
+                "index experiment",
+                index_experiment_leveldb(&database.leveldb, &resp, &bounds),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "RocksDB",
+                "index experiment",
+                index_experiment_rocksdb(&database.rocksdb, &resp, &bounds),
+            )
+            .await;
+    }
+
+    Ok(report)
+}
+
+pub async fn index_experiment(
+    Extension(database): Extension<Arc<DataBases>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    respond(experiment(&database, &config, &Backend::ALL).await)
+}
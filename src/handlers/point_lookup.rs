@@ -0,0 +1,249 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{extract::Query, response::IntoResponse, Extension};
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    db::{
+        leveldb::LevelDB, mongodb::MongoDB, postgres::PostgreSQL, rocksdb::RocksDB,
+        surrealdb::SurrealDBWrapper, Backend, DataBases,
+    },
+    handlers::{respond, stored_start_times, Outcome, Report},
+    metrics::latency_metrics::latency_metrics,
+    utils::{
+        key_sampler::{sample_keys, KeyDistribution},
+        kv_keys::depth_history_key,
+        run_events::error,
+        types::{Error, Result},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct PointLookupParams {
+    pub count: Option<usize>,
+    pub distribution: Option<KeyDistribution>,
+}
+
+fn lookup_message(database: &str, distribution: KeyDistribution, count: usize) -> String {
+    format!(
+        "Point lookup latency for {} depth history ({}, {} lookups) : ",
+        database,
+        distribution.as_str(),
+        count
+    )
+}
+
+fn missing_record(database: &str, start_time: i64) -> Error {
+    Error::DataBaseReadFailed(format!(
+        "{} has no depth history record with start time {}",
+        database, start_time
+    ))
+}
+
+async fn point_lookup_mongodb(mongodb: &MongoDB, keys: &[i64]) -> Result<Vec<Duration>> {
+    let mut samples = Vec::with_capacity(keys.len());
+
+    for &start_time in keys {
+        let start = Instant::now();
+        let record = mongodb.find_depth_history_by_start_time(start_time).await?;
+        samples.push(start.elapsed());
+
+        if record.is_none() {
+            return Err(missing_record("MongoDB", start_time));
+        }
+    }
+
+    Ok(samples)
+}
+
+async fn point_lookup_postgres(postgres: &PostgreSQL, keys: &[i64]) -> Result<Vec<Duration>> {
+    let mut samples = Vec::with_capacity(keys.len());
+
+    for &start_time in keys {
+        let start = Instant::now();
+        let record = postgres
+            .read_depth_history_by_start_time(start_time)
+            .await?;
+        samples.push(start.elapsed());
+
+        if record.is_none() {
+            return Err(missing_record("Postgres", start_time));
+        }
+    }
+
+    Ok(samples)
+}
+
+async fn point_lookup_surrealdb(
+    surrealdb: &SurrealDBWrapper,
+    keys: &[i64],
+) -> Result<Vec<Duration>> {
+    let mut samples = Vec::with_capacity(keys.len());
+
+    for &start_time in keys {
+        let start = Instant::now();
+        let record = surrealdb
+            .read_depth_history_by_start_time(start_time)
+            .await?;
+        samples.push(start.elapsed());
+
+        if record.is_none() {
+            return Err(missing_record("SurrealDB", start_time));
+        }
+    }
+
+    Ok(samples)
+}
+
+async fn point_lookup_leveldb(leveldb: &LevelDB, keys: &[i64]) -> Result<Vec<Duration>> {
+    let mut samples = Vec::with_capacity(keys.len());
+
+    for &start_time in keys {
+        let key = depth_history_key(start_time);
+
+        let start = Instant::now();
+        let record = leveldb.get_data(key).await?;
+        samples.push(start.elapsed());
+
+        if record.is_none() {
+            return Err(missing_record("LevelDB", start_time));
+        }
+    }
+
+    Ok(samples)
+}
+
+async fn point_lookup_rocksdb(rocksdb: &RocksDB, keys: &[i64]) -> Result<Vec<Duration>> {
+    let mut samples = Vec::with_capacity(keys.len());
+
+    for &start_time in keys {
+        let key = depth_history_key(start_time);
+
+        let start = Instant::now();
+        let record = rocksdb.get_data(key).await?;
+        samples.push(start.elapsed());
+
+        if record.is_none() {
+            return Err(missing_record("RocksDB", start_time));
+        }
+    }
+
+    Ok(samples)
+}
+
+// Looks up `count` sampled start times one at a time in the selected backends, used by the route
+// and the `run` command.
+pub async fn lookup(
+    database: &DataBases,
+    backends: &[Backend],
+    count: usize,
+    distribution: KeyDistribution,
+) -> Outcome {
+    let mut report = Report::new("Completed point lookups");
+
+    // the sampled start times are looked up in every selected backend
+    let stored_keys = stored_start_times(database, backends, &mut report).await;
+
+    let keys = match sample_keys(&stored_keys, count, distribution) {
+        Ok(keys) => keys,
+        // no backend answered, their failures are the result
+        Err(_) if report.failures() > 0 => return Ok(report),
+        Err(err) => {
+            error(format!("Point lookups aborted: {:?}", err));
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "No stored data to look up, insert data first",
+            ));
+        }
+    };
+
+    if backends.contains(&Backend::MongoDB) {
+        if let Some(samples) = report
+            .attempt(
+                database.operation_timeout,
+                "MongoDB",
+                "point lookup",
+                point_lookup_mongodb(&database.mongodb, &keys),
+            )
+            .await
+        {
+            latency_metrics(samples, &lookup_message("MongoDB", distribution, count));
+        }
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        if let Some(samples) = report
+            .attempt(
+                database.operation_timeout,
+                "Postgres",
+                "point lookup",
+                point_lookup_postgres(&database.postgres, &keys),
+            )
+            .await
+        {
+            latency_metrics(samples, &lookup_message("Postgres", distribution, count));
+        }
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        if let Some(samples) = report
+            .attempt(
+                database.operation_timeout,
+                "SurrealDB",
+                "point lookup",
+                point_lookup_surrealdb(&database.surrealdb, &keys),
+            )
+            .await
+        {
+            latency_metrics(samples, &lookup_message("SurrealDB", distribution, count));
+        }
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        if let Some(samples) = report
+            .attempt(
+                database.operation_timeout,
+                "LevelDB",
+                "point lookup",
+                point_lookup_leveldb(&database.leveldb, &keys),
+            )
+            .await
+        {
+            latency_metrics(samples, &lookup_message("LevelDB", distribution, count));
+        }
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        if let Some(samples) = report
+            .attempt(
+                database.operation_timeout,
+                "RocksDB",
+                "point lookup",
+                point_lookup_rocksdb(&database.rocksdb, &keys),
+            )
+            .await
+        {
+            latency_metrics(samples, &lookup_message("RocksDB", distribution, count));
+        }
+    }
+
+    Ok(report)
+}
+
+pub async fn point_lookup(
+    Extension(database): Extension<Arc<DataBases>>,
+    Extension(config): Extension<Arc<Config>>,
+    Query(params): Query<PointLookupParams>,
+) -> impl IntoResponse {
+    let count = params.count.unwrap_or(config.workloads.point_lookup_count);
+    let distribution = params
+        .distribution
+        .unwrap_or(config.workloads.key_distribution);
+
+    respond(lookup(&database, &Backend::ALL, count, distribution).await)
+}
@@ -1,189 +1,378 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use axum::{response::IntoResponse, Extension};
-use reqwest::StatusCode;
+use axum::{extract::Query, response::IntoResponse, Extension};
+use serde::Deserialize;
 
 use crate::{
+    config::Config,
     db::{
         leveldb::LevelDB, mongodb::MongoDB, postgres::PostgreSQL, rocksdb::RocksDB,
-        surrealdb::SurrealDBWrapper, DataBases,
+        surrealdb::SurrealDBWrapper, Backend, DataBases,
     },
+    handlers::{respond, Outcome, Report},
     metrics::performance_metrics::performance_metrics,
     utils::types::Result,
 };
 
-async fn read_data_from_mongodb(mongodb: &MongoDB) -> Result<bool> {
+async fn read_data_from_mongodb(mongodb: &MongoDB, phase: &str) -> Result<bool> {
     // reading depth history data...
 
     let start_time = Instant::now();
 
-    mongodb.read_depth_history().await.unwrap();
+    let records = mongodb.read_depth_history().await?.len();
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for MongoDB to read depth history data (400 records) : ",
+        &format!(
+            "Time taken for MongoDB to read depth history data ({} records, {}) : ",
+            records, phase
+        ),
     );
 
     // reading rune pool data...
     let start_time = Instant::now();
 
-    mongodb.read_rune_pool_history().await.unwrap();
+    let records = mongodb.read_rune_pool_history().await?.len();
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for MongoDB to read rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for MongoDB to read rune pool history data ({} records, {}) : ",
+            records, phase
+        ),
     );
 
     Ok(true)
 }
 
-pub async fn read_data_from_postgres(postgres: &PostgreSQL) -> Result<bool> {
+pub async fn read_data_from_postgres(postgres: &PostgreSQL, phase: &str) -> Result<bool> {
     // reading depth history data...
     let start_time = Instant::now();
 
-    postgres.read_depth_history().await.map_err(|e| e)?;
+    let records = postgres.read_depth_history().await?.len();
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for Postgres to read depth history data (400 records) : ",
+        &format!(
+            "Time taken for Postgres to read depth history data ({} records, {}) : ",
+            records, phase
+        ),
     );
 
     // reading rune pool history data...
     let start_time = Instant::now();
 
-    let _ = postgres.read_rune_pool_history().await.map_err(|e| e)?;
+    let records = postgres.read_rune_pool_history().await?.len();
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for Postgres to read rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for Postgres to read rune pool history data ({} records, {}) : ",
+            records, phase
+        ),
     );
 
     Ok(true)
 }
 
-pub async fn read_data_from_surrealdb(surrealdb: &SurrealDBWrapper) -> Result<bool> {
+pub async fn read_data_from_surrealdb(surrealdb: &SurrealDBWrapper, phase: &str) -> Result<bool> {
     let start_time = Instant::now();
 
-    surrealdb.read_depth_history().await.map_err(|e| e)?;
+    let records = surrealdb.read_depth_history().await?.len();
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for SurrealDB to read depth history data (400 records) : ",
+        &format!(
+            "Time taken for {} to read depth history data ({} records, {}) : ",
+            surrealdb.name, records, phase
+        ),
     );
 
     // reading rune pool history data...
     let start_time = Instant::now();
 
-    let _ = surrealdb.read_rune_pool_history().await.map_err(|e| e)?;
+    let records = surrealdb.read_rune_pool_history().await?.len();
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for SurrealDB to read rune pool history data (400 records) : ",
+        &format!(
+            "Time taken for {} to read rune pool history data ({} records, {}) : ",
+            surrealdb.name, records, phase
+        ),
     );
 
     Ok(true)
 }
 
-pub async fn read_data_from_rocksdb(rocksdb: &RocksDB) -> Result<bool> {
+pub async fn read_data_from_rocksdb(rocksdb: &RocksDB, phase: &str) -> Result<bool> {
     let start_time = Instant::now();
 
-    rocksdb.read_data().await.map_err(|e| e)?;
+    let records = rocksdb.read_data().await?;
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for RocksDB to read data (400 records) : ",
+        &format!(
+            "Time taken for RocksDB to read data ({} entries, {}) : ",
+            records, phase
+        ),
     );
 
     Ok(true)
 }
 
-pub async fn read_data_from_leveldb(leveldb: &LevelDB) -> Result<bool> {
+pub async fn read_data_from_leveldb(leveldb: &LevelDB, phase: &str) -> Result<bool> {
     let start_time = Instant::now();
 
-    leveldb.read_data().await.map_err(|e| e)?;
+    let records = leveldb.read_data().await?;
 
     let end_time = Instant::now();
 
     performance_metrics(
         start_time,
         end_time,
-        "Time taken for LevelDB to read data (400 records) : ",
+        &format!(
+            "Time taken for LevelDB to read data ({} entries, {}) : ",
+            records, phase
+        ),
     );
 
     Ok(true)
 }
-pub async fn read_data(Extension(database): Extension<Arc<DataBases>>) -> impl IntoResponse {
-    let mongodb = &database.mongodb;
-    let postgres = &database.postgres;
-    let surrealdb = &database.surrealdb;
-    let leveldb = &database.leveldb;
-    let rocksdb = &database.rocksdb;
-
-    let _ = read_data_from_mongodb(mongodb).await.map_err(|e| {
-        eprintln!("{:?}", e);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read data from mongodb",
-        )
-            .into_response();
-    });
-
-    let _ = read_data_from_postgres(postgres).await.map_err(|e| {
-        eprintln!("{:?}", e);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read data from postgres",
-        )
-            .into_response();
-    });
-
-    let _ = read_data_from_surrealdb(surrealdb).await.map_err(|e| {
-        eprintln!("{:?}", e);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read data from surrealDB",
-        )
-            .into_response();
-    });
-
-    let _ = read_data_from_rocksdb(rocksdb).await.map_err(|e| {
-        eprintln!("{:?}", e);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read data from RocksDB",
-        )
-            .into_response();
-    });
-
-    let _ = read_data_from_leveldb(leveldb).await.map_err(|e| {
-        eprintln!("{:?}", e);
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to read data from LevelDB",
-        )
-            .into_response();
-    });
+#[derive(Debug, Deserialize)]
+pub struct ReadDataParams {
+    // closes and reopens RocksDB and LevelDB before reading, then reads again warm
+    pub cold: Option<bool>,
+    // with cold=true, also reads through fresh MongoDB, Postgres and SurrealDB connections
+    pub reconnect: Option<bool>,
+}
+
+// Backends a read pass goes to, cold reads with `reconnect` use fresh server connections.
+// Servers are None when they could not be reconnected for cold reads, they are left out then.
+struct ReadTargets<'a> {
+    mongodb: Option<&'a MongoDB>,
+    postgres: Option<&'a PostgreSQL>,
+    surrealdb: Option<&'a SurrealDBWrapper>,
+    surrealdb_embedded: &'a [SurrealDBWrapper],
+    leveldb: &'a LevelDB,
+    rocksdb: &'a RocksDB,
+    operation_timeout: Option<Duration>,
+}
+
+impl<'a> ReadTargets<'a> {
+    fn of(database: &'a DataBases) -> Self {
+        ReadTargets {
+            mongodb: Some(&database.mongodb),
+            postgres: Some(&database.postgres),
+            surrealdb: Some(&database.surrealdb),
+            surrealdb_embedded: &database.surrealdb_embedded,
+            leveldb: &database.leveldb,
+            rocksdb: &database.rocksdb,
+            operation_timeout: database.operation_timeout,
+        }
+    }
+}
 
-    (StatusCode::OK, "Fetched data").into_response()
+async fn read_from_all(
+    targets: &ReadTargets<'_>,
+    backends: &[Backend],
+    phase: &str,
+    report: &mut Report,
+) {
+    let operation = format!("{} read", phase);
+
+    if let Some(mongodb) = targets
+        .mongodb
+        .filter(|_| backends.contains(&Backend::MongoDB))
+    {
+        report
+            .attempt(
+                targets.operation_timeout,
+                "MongoDB",
+                &operation,
+                read_data_from_mongodb(mongodb, phase),
+            )
+            .await;
+    }
+
+    if let Some(postgres) = targets
+        .postgres
+        .filter(|_| backends.contains(&Backend::Postgres))
+    {
+        report
+            .attempt(
+                targets.operation_timeout,
+                "Postgres",
+                &operation,
+                read_data_from_postgres(postgres, phase),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        if let Some(surrealdb) = targets.surrealdb {
+            report
+                .attempt(
+                    targets.operation_timeout,
+                    "SurrealDB",
+                    &operation,
+                    read_data_from_surrealdb(surrealdb, phase),
+                )
+                .await;
+        }
+
+        for surrealdb in targets.surrealdb_embedded {
+            report
+                .attempt(
+                    targets.operation_timeout,
+                    &surrealdb.name,
+                    &operation,
+                    read_data_from_surrealdb(surrealdb, phase),
+                )
+                .await;
+        }
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(
+                targets.operation_timeout,
+                "RocksDB",
+                &operation,
+                read_data_from_rocksdb(targets.rocksdb, phase),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(
+                targets.operation_timeout,
+                "LevelDB",
+                &operation,
+                read_data_from_leveldb(targets.leveldb, phase),
+            )
+            .await;
+    }
+}
+
+// Cold reads go to freshly opened embedded stores and, with `reconnect`, to new server
+// connections. Server side caches and the OS page cache stay warm.
+async fn read_cold(
+    database: &DataBases,
+    config: &Config,
+    backends: &[Backend],
+    reconnect: bool,
+    report: &mut Report,
+) {
+    if backends.contains(&Backend::RocksDB) {
+        report.record("RocksDB", "reopen", database.rocksdb.reopen().await);
+    }
+    if backends.contains(&Backend::LevelDB) {
+        report.record("LevelDB", "reopen", database.leveldb.reopen().await);
+    }
+
+    if !reconnect {
+        read_from_all(&ReadTargets::of(database), backends, "cold", report).await;
+        return;
+    }
+
+    // a server that cannot be reached again fails its reconnect and is left out of the cold
+    // reads, the other backends are still read
+    let timeout = database.operation_timeout;
+    let mongodb = match backends.contains(&Backend::MongoDB) {
+        true => {
+            let connect = MongoDB::init(&config.mongodb, config.durability);
+            report
+                .attempt(timeout, "MongoDB", "reconnect", connect)
+                .await
+        }
+        false => None,
+    };
+    let postgres = match backends.contains(&Backend::Postgres) {
+        true => {
+            let connect = PostgreSQL::init(&config.postgres, config.durability);
+            report
+                .attempt(timeout, "Postgres", "reconnect", connect)
+                .await
+        }
+        false => None,
+    };
+    let surrealdb = match backends.contains(&Backend::SurrealDB) {
+        true => {
+            let connect = SurrealDBWrapper::init(&config.surrealdb);
+            report
+                .attempt(timeout, "SurrealDB", "reconnect", connect)
+                .await
+        }
+        false => None,
+    };
+
+    let targets = ReadTargets {
+        mongodb: mongodb.as_ref(),
+        postgres: postgres.as_ref(),
+        surrealdb: surrealdb.as_ref(),
+        ..ReadTargets::of(database)
+    };
+    read_from_all(&targets, backends, "cold", report).await;
+}
+
+// Reads the stored data back from the selected backends, optionally cold first, used by the
+// route and the `read` command.
+pub async fn read(
+    database: &DataBases,
+    config: &Config,
+    backends: &[Backend],
+    cold: bool,
+    reconnect: bool,
+) -> Outcome {
+    let mut report = Report::new("Fetched data");
+
+    if cold {
+        read_cold(database, config, backends, reconnect, &mut report).await;
+    }
+
+    read_from_all(&ReadTargets::of(database), backends, "warm", &mut report).await;
+
+    Ok(report)
+}
+
+pub async fn read_data(
+    Extension(database): Extension<Arc<DataBases>>,
+    Extension(config): Extension<Arc<Config>>,
+    Query(params): Query<ReadDataParams>,
+) -> impl IntoResponse {
+    respond(
+        read(
+            &database,
+            &config,
+            &Backend::ALL,
+            params.cold.unwrap_or(false),
+            params.reconnect.unwrap_or(false),
+        )
+        .await,
+    )
 }
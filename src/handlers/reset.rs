@@ -0,0 +1,23 @@
+use std::sync::Arc;
+
+use axum::{response::IntoResponse, Extension};
+
+use crate::{
+    db::{reset_databases, Backend, DataBases},
+    handlers::{respond, Outcome, Report},
+};
+
+// Empties the selected backends, used by the route and the `reset` command.
+pub async fn reset_backends(database: &DataBases, backends: &[Backend]) -> Outcome {
+    let mut report = Report::new("Reset databases");
+
+    for (backend, result) in reset_databases(database, backends).await {
+        report.record(&backend, "reset", result);
+    }
+
+    Ok(report)
+}
+
+pub async fn reset(Extension(database): Extension<Arc<DataBases>>) -> impl IntoResponse {
+    respond(reset_backends(&database, &Backend::ALL).await)
+}
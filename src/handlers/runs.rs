@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Path,
+    http::header::LOCATION,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Extension, Json,
+};
+use reqwest::StatusCode;
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    config::Config,
+    db::DataBases,
+    runs::{Cancellation, RunRequest, Runs},
+    utils::run_events::RunEvent,
+};
+
+// Starts a run in the background and answers right away with its id.
+pub async fn create_run(
+    Extension(database): Extension<Arc<DataBases>>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(runs): Extension<Arc<Runs>>,
+    Json(request): Json<RunRequest>,
+) -> impl IntoResponse {
+    let id = runs.start(database, config, request);
+
+    (
+        StatusCode::ACCEPTED,
+        [(LOCATION, format!("/runs/{}", id))],
+        Json(json!({ "id": id })),
+    )
+        .into_response()
+}
+
+pub async fn get_run(
+    Extension(runs): Extension<Arc<Runs>>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match runs.report(id) {
+        Some(report) => Json(report).into_response(),
+        None => (StatusCode::NOT_FOUND, "Unknown run").into_response(),
+    }
+}
+
+pub async fn cancel_run(
+    Extension(runs): Extension<Arc<Runs>>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match runs.cancel(id) {
+        Cancellation::Requested => (StatusCode::ACCEPTED, "Cancelling run"),
+        Cancellation::AlreadyFinished => (StatusCode::CONFLICT, "Run already finished"),
+        Cancellation::UnknownRun => (StatusCode::NOT_FOUND, "Unknown run"),
+    }
+}
+
+fn sse_event(event: &RunEvent) -> Result<Event, axum::Error> {
+    Event::default().event(event.name()).json_data(event)
+}
+
+// Streams the events of a run as Server-Sent Events, starting with the ones already sent. The
+// stream ends after the `run-finished` event.
+pub async fn run_events(
+    Extension(runs): Extension<Arc<Runs>>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    let Some(events) = runs.events(id) else {
+        return (StatusCode::NOT_FOUND, "Unknown run").into_response();
+    };
+
+    let (history, mut receiver) = events.subscribe();
+
+    let stream = async_stream::stream! {
+        for event in history {
+            yield sse_event(&event);
+            if let RunEvent::RunFinished { .. } = event {
+                return;
+            }
+        }
+
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    yield sse_event(&event);
+                    if let RunEvent::RunFinished { .. } = event {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    yield sse_event(&RunEvent::Error {
+                        message: format!("{} events skipped, the stream fell behind", skipped),
+                    });
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
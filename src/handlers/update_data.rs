@@ -0,0 +1,367 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{extract::Query, response::IntoResponse, Extension};
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    db::{
+        leveldb::LevelDB, mongodb::MongoDB, postgres::PostgreSQL, rocksdb::RocksDB,
+        surrealdb::SurrealDBWrapper, Backend, DataBases,
+    },
+    handlers::{respond, stored_start_times, Outcome, Report},
+    metrics::performance_metrics::performance_metrics,
+    models::depth_history_model::DepthHistory,
+    utils::{
+        kv_keys::depth_history_key,
+        types::{Error, Result},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDataParams {
+    pub factor: Option<f64>,
+}
+
+async fn update_depth_history_in_mongodb(
+    mongodb: &MongoDB,
+    start_times: &[i64],
+    factor: f64,
+) -> Result<bool> {
+    let (one_by_one, bulk_from) = split_start_times(start_times);
+
+    let start_time = Instant::now();
+    for &interval_start in one_by_one {
+        mongodb
+            .scale_depth_history_price(interval_start, factor)
+            .await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for MongoDB to update depth history data one by one (update_one, {} records) : ",
+            one_by_one.len()
+        ),
+    );
+
+    if let Some(bulk_from) = bulk_from {
+        let start_time = Instant::now();
+        mongodb
+            .scale_depth_history_prices_from(bulk_from, factor)
+            .await?;
+        let end_time = Instant::now();
+
+        performance_metrics(
+            start_time,
+            end_time,
+            &format!(
+                "Time taken for MongoDB to update depth history data in bulk (update_many, {} records) : ",
+                start_times.len() - one_by_one.len()
+            ),
+        );
+    }
+
+    Ok(true)
+}
+
+async fn update_depth_history_in_postgres(
+    postgres: &PostgreSQL,
+    start_times: &[i64],
+    factor: f64,
+) -> Result<bool> {
+    let (one_by_one, bulk_from) = split_start_times(start_times);
+
+    let start_time = Instant::now();
+    for &interval_start in one_by_one {
+        postgres
+            .scale_depth_history_price(interval_start, factor)
+            .await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for Postgres to update depth history data one by one (UPDATE ... WHERE, {} records) : ",
+            one_by_one.len()
+        ),
+    );
+
+    if let Some(bulk_from) = bulk_from {
+        let start_time = Instant::now();
+        postgres
+            .scale_depth_history_prices_from(bulk_from, factor)
+            .await?;
+        let end_time = Instant::now();
+
+        performance_metrics(
+            start_time,
+            end_time,
+            &format!(
+                "Time taken for Postgres to update depth history data in bulk (single UPDATE, {} records) : ",
+                start_times.len() - one_by_one.len()
+            ),
+        );
+    }
+
+    Ok(true)
+}
+
+async fn update_depth_history_in_surrealdb(
+    surrealdb: &SurrealDBWrapper,
+    start_times: &[i64],
+    factor: f64,
+) -> Result<bool> {
+    let (one_by_one, bulk_from) = split_start_times(start_times);
+
+    let start_time = Instant::now();
+    for &interval_start in one_by_one {
+        surrealdb
+            .scale_depth_history_price(interval_start, factor)
+            .await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for SurrealDB to update depth history data one by one (UPDATE ... WHERE, {} records) : ",
+            one_by_one.len()
+        ),
+    );
+
+    if let Some(bulk_from) = bulk_from {
+        let start_time = Instant::now();
+        surrealdb
+            .scale_depth_history_prices_from(bulk_from, factor)
+            .await?;
+        let end_time = Instant::now();
+
+        performance_metrics(
+            start_time,
+            end_time,
+            &format!(
+                "Time taken for SurrealDB to update depth history data in bulk (single UPDATE, {} records) : ",
+                start_times.len() - one_by_one.len()
+            ),
+        );
+    }
+
+    Ok(true)
+}
+
+// Every stored interval is scaled exactly once per update, so all backends hold the same prices
+// afterwards. The backends with an update statement scale the first half of the sorted start
+// times one record at a time and the rest with a single bulk statement from the split point on.
+fn split_start_times(start_times: &[i64]) -> (&[i64], Option<i64>) {
+    let (one_by_one, bulk) = start_times.split_at(start_times.len() / 2);
+    (one_by_one, bulk.first().copied())
+}
+
+// The key-value stores have no update statement, each record is read, modified and written back.
+fn scale_kv_value(val: &[u8], factor: f64, database: &str) -> Result<Vec<u8>> {
+    let mut depth_history: DepthHistory = serde_json::from_slice(val).map_err(|e| {
+        Error::DataBaseReadFailed(format!(
+            "Failed to deserialize depth history from {}: {:?}",
+            database, e
+        ))
+    })?;
+
+    depth_history.asset_price_usd *= factor;
+
+    serde_json::to_vec(&depth_history).map_err(|e| {
+        Error::DataBaseInsertionFailed(format!(
+            "Failed to serialize depth history for {}: {:?}",
+            database, e
+        ))
+    })
+}
+
+async fn update_depth_history_in_leveldb(
+    leveldb: &LevelDB,
+    start_times: &[i64],
+    factor: f64,
+) -> Result<bool> {
+    let start_time = Instant::now();
+    for &interval_start in start_times {
+        let key = depth_history_key(interval_start);
+
+        if let Some(val) = leveldb.get_data(key.clone()).await? {
+            let updated = scale_kv_value(&val, factor, "LevelDB")?;
+            leveldb.update_data(key, updated).await?;
+        }
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for LevelDB to update depth history data (read-modify-write, {} records) : ",
+            start_times.len()
+        ),
+    );
+
+    Ok(true)
+}
+
+async fn update_depth_history_in_rocksdb(
+    rocksdb: &RocksDB,
+    start_times: &[i64],
+    factor: f64,
+) -> Result<bool> {
+    let start_time = Instant::now();
+    for &interval_start in start_times {
+        let key = depth_history_key(interval_start);
+
+        if let Some(val) = rocksdb.get_data(key.clone()).await? {
+            let updated = scale_kv_value(&val, factor, "RocksDB")?;
+            rocksdb.insert_data(key, updated).await?;
+        }
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for RocksDB to update depth history data (read-modify-write, {} records) : ",
+            start_times.len()
+        ),
+    );
+
+    Ok(true)
+}
+
+// Scales `asset_price_usd` of every stored interval in the selected backends, used by the route
+// and the `run` command.
+pub async fn update(database: &DataBases, backends: &[Backend], factor: f64) -> Outcome {
+    let mut report = Report::new("Updated data");
+
+    // every stored interval is updated in each selected backend
+    let mut start_times = stored_start_times(database, backends, &mut report).await;
+    if start_times.is_empty() && report.failures() > 0 {
+        // no backend answered, their failures are the result
+        return Ok(report);
+    }
+    start_times.sort();
+    start_times.dedup();
+
+    if backends.contains(&Backend::MongoDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "MongoDB",
+                "update",
+                update_depth_history_in_mongodb(&database.mongodb, &start_times, factor),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "Postgres",
+                "update",
+                update_depth_history_in_postgres(&database.postgres, &start_times, factor),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "SurrealDB",
+                "update",
+                update_depth_history_in_surrealdb(&database.surrealdb, &start_times, factor),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "LevelDB",
+                "update",
+                update_depth_history_in_leveldb(&database.leveldb, &start_times, factor),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "RocksDB",
+                "update",
+                update_depth_history_in_rocksdb(&database.rocksdb, &start_times, factor),
+            )
+            .await;
+    }
+
+    Ok(report)
+}
+
+pub async fn update_data(
+    Extension(database): Extension<Arc<DataBases>>,
+    Extension(config): Extension<Arc<Config>>,
+    Query(params): Query<UpdateDataParams>,
+) -> impl IntoResponse {
+    let factor = params
+        .factor
+        .unwrap_or(config.workloads.update_price_factor);
+
+    respond(update(&database, &Backend::ALL, factor).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    // Applies the update of the statement backends and of the key-value stores to the same prices.
+    fn updated_prices(prices: &BTreeMap<i64, f64>, factor: f64) -> [BTreeMap<i64, f64>; 2] {
+        let start_times: Vec<i64> = prices.keys().copied().collect();
+
+        let mut statements = prices.clone();
+        let (one_by_one, bulk_from) = split_start_times(&start_times);
+        for start_time in one_by_one {
+            *statements.get_mut(start_time).unwrap() *= factor;
+        }
+        if let Some(bulk_from) = bulk_from {
+            for price in statements.range_mut(bulk_from..).map(|(_, price)| price) {
+                *price *= factor;
+            }
+        }
+
+        let mut key_value = prices.clone();
+        for start_time in &start_times {
+            *key_value.get_mut(start_time).unwrap() *= factor;
+        }
+
+        [statements, key_value]
+    }
+
+    #[test]
+    fn every_backend_scales_each_price_once() {
+        for count in 0..5 {
+            let prices: BTreeMap<i64, f64> =
+                (0..count).map(|i| (i * 3600, 1.5 + i as f64)).collect();
+            let [statements, key_value] = updated_prices(&prices, 1.01);
+
+            assert_eq!(statements, key_value);
+            for (start_time, price) in &prices {
+                assert_eq!(statements[start_time], price * 1.01);
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{response::IntoResponse, Extension};
+use reqwest::StatusCode;
+
+use crate::{
+    config::Config,
+    db::{
+        leveldb::LevelDB, mongodb::MongoDB, postgres::PostgreSQL, rocksdb::RocksDB,
+        surrealdb::SurrealDBWrapper, Backend, DataBases,
+    },
+    handlers::{respond, Outcome, Report},
+    metrics::performance_metrics::performance_metrics,
+    models::{
+        depth_history_model::DepthHistoryResponse, rune_pool_history_model::RunePoolHistoryResponse,
+    },
+    utils::{
+        kv_keys::{depth_history_key, rune_pool_history_key},
+        run_events::error,
+        types::{Error, Result},
+    },
+};
+
+async fn upsert_into_mongodb(
+    mongodb: &MongoDB,
+    depth_history: &DepthHistoryResponse,
+    rune_pool_history: &RunePoolHistoryResponse,
+) -> Result<bool> {
+    let start_time = Instant::now();
+    for record in &depth_history.intervals {
+        mongodb.upsert_depth_history(record).await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for MongoDB to upsert depth history data ({} records) : ",
+            depth_history.intervals.len()
+        ),
+    );
+
+    let start_time = Instant::now();
+    for record in &rune_pool_history.intervals {
+        mongodb.upsert_rune_pool_history(record).await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for MongoDB to upsert rune pool history data ({} records) : ",
+            rune_pool_history.intervals.len()
+        ),
+    );
+
+    Ok(true)
+}
+
+async fn upsert_into_postgres(
+    postgres: &PostgreSQL,
+    depth_history: &DepthHistoryResponse,
+    rune_pool_history: &RunePoolHistoryResponse,
+) -> Result<bool> {
+    let start_time = Instant::now();
+    for record in &depth_history.intervals {
+        postgres.upsert_depth_history(record).await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for Postgres to upsert depth history data ({} records) : ",
+            depth_history.intervals.len()
+        ),
+    );
+
+    let start_time = Instant::now();
+    for record in &rune_pool_history.intervals {
+        postgres.upsert_rune_pool_history(record).await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for Postgres to upsert rune pool history data ({} records) : ",
+            rune_pool_history.intervals.len()
+        ),
+    );
+
+    Ok(true)
+}
+
+async fn upsert_into_surrealdb(
+    surrealdb: &SurrealDBWrapper,
+    depth_history: &DepthHistoryResponse,
+    rune_pool_history: &RunePoolHistoryResponse,
+) -> Result<bool> {
+    let start_time = Instant::now();
+    for record in &depth_history.intervals {
+        surrealdb.upsert_depth_history(record).await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for SurrealDB to upsert depth history data ({} records) : ",
+            depth_history.intervals.len()
+        ),
+    );
+
+    let start_time = Instant::now();
+    for record in &rune_pool_history.intervals {
+        surrealdb.upsert_rune_pool_history(record).await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for SurrealDB to upsert rune pool history data ({} records) : ",
+            rune_pool_history.intervals.len()
+        ),
+    );
+
+    Ok(true)
+}
+
+fn serialize_value<T: serde::Serialize>(record: &T, database: &str) -> Result<Vec<u8>> {
+    serde_json::to_vec(record).map_err(|e| {
+        Error::DataBaseInsertionFailed(format!(
+            "Failed to serialize record for {}: {:?}",
+            database, e
+        ))
+    })
+}
+
+// A put into a key-value store already replaces any existing value, so upserting is the same
+// operation as inserting.
+async fn upsert_into_leveldb(
+    leveldb: &LevelDB,
+    depth_history: &DepthHistoryResponse,
+    rune_pool_history: &RunePoolHistoryResponse,
+) -> Result<bool> {
+    let start_time = Instant::now();
+    for record in &depth_history.intervals {
+        let val = serialize_value(record, "LevelDB")?;
+        leveldb
+            .insert_data(depth_history_key(record.start_time), val)
+            .await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for LevelDB to upsert depth history data ({} records) : ",
+            depth_history.intervals.len()
+        ),
+    );
+
+    let start_time = Instant::now();
+    for record in &rune_pool_history.intervals {
+        let val = serialize_value(record, "LevelDB")?;
+        leveldb
+            .insert_data(rune_pool_history_key(record.start_time), val)
+            .await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for LevelDB to upsert rune pool history data ({} records) : ",
+            rune_pool_history.intervals.len()
+        ),
+    );
+
+    Ok(true)
+}
+
+async fn upsert_into_rocksdb(
+    rocksdb: &RocksDB,
+    depth_history: &DepthHistoryResponse,
+    rune_pool_history: &RunePoolHistoryResponse,
+) -> Result<bool> {
+    let start_time = Instant::now();
+    for record in &depth_history.intervals {
+        let val = serialize_value(record, "RocksDB")?;
+        rocksdb
+            .insert_data(depth_history_key(record.start_time), val)
+            .await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for RocksDB to upsert depth history data ({} records) : ",
+            depth_history.intervals.len()
+        ),
+    );
+
+    let start_time = Instant::now();
+    for record in &rune_pool_history.intervals {
+        let val = serialize_value(record, "RocksDB")?;
+        rocksdb
+            .insert_data(rune_pool_history_key(record.start_time), val)
+            .await?;
+    }
+    let end_time = Instant::now();
+
+    performance_metrics(
+        start_time,
+        end_time,
+        &format!(
+            "Time taken for RocksDB to upsert rune pool history data ({} records) : ",
+            rune_pool_history.intervals.len()
+        ),
+    );
+
+    Ok(true)
+}
+
+// Fetches the Midgard window again and upserts it into the selected backends, used by the route
+// and the `run` command.
+pub async fn upsert(database: &DataBases, config: &Config, backends: &[Backend]) -> Outcome {
+    let mut report = Report::new("Upserted data");
+
+    let depth_history = match database
+        .midgard
+        .fetch_depth_history(&config.datasets.depth_history_url)
+        .await
+    {
+        Ok(resp) => resp,
+        Err(err) => {
+            error(format!("Failed to fetch depth history data: {:?}", err));
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to fetch depth history data",
+            ));
+        }
+    };
+
+    let rune_pool_history = match database
+        .midgard
+        .fetch_rune_pool_history(&config.datasets.rune_pool_history_url)
+        .await
+    {
+        Ok(resp) => resp,
+        Err(err) => {
+            error(format!("Failed to fetch rune pool history data: {:?}", err));
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to fetch rune pool history data",
+            ));
+        }
+    };
+
+    if backends.contains(&Backend::MongoDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "MongoDB",
+                "upsert",
+                upsert_into_mongodb(&database.mongodb, &depth_history, &rune_pool_history),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::Postgres) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "Postgres",
+                "upsert",
+                upsert_into_postgres(&database.postgres, &depth_history, &rune_pool_history),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::SurrealDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "SurrealDB",
+                "upsert",
+                upsert_into_surrealdb(&database.surrealdb, &depth_history, &rune_pool_history),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::LevelDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "LevelDB",
+                "upsert",
+                upsert_into_leveldb(&database.leveldb, &depth_history, &rune_pool_history),
+            )
+            .await;
+    }
+
+    if backends.contains(&Backend::RocksDB) {
+        report
+            .attempt(
+                database.operation_timeout,
+                "RocksDB",
+                "upsert",
+                upsert_into_rocksdb(&database.rocksdb, &depth_history, &rune_pool_history),
+            )
+            .await;
+    }
+
+    Ok(report)
+}
+
+pub async fn upsert_data(
+    Extension(database): Extension<Arc<DataBases>>,
+    Extension(config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    respond(upsert(&database, &config, &Backend::ALL).await)
+}
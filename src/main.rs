@@ -1,32 +1,125 @@
 use std::sync::Arc;
 
-use axum::{routing::get, Extension, Router};
-use db::init_databases;
-use handlers::{fetch_and_insert_data::fetch_and_insert_data, read_data::read_data};
+use axum::{
+    middleware,
+    routing::{get, post},
+    Extension, Router,
+};
+use clap::Parser;
+use cli::{Cli, Command};
+use config::Config;
+use db::{init_databases, postgres::PostgreSQL};
+use handlers::{
+    aggregate_data::aggregate_data,
+    fetch_and_insert_data::fetch_and_insert_data,
+    index_experiment::index_experiment,
+    point_lookup::point_lookup,
+    read_data::read_data,
+    reset::reset,
+    runs::{cancel_run, create_run, get_run, run_events},
+    update_data::update_data,
+    upsert_data::upsert_data,
+};
+use metrics::resource_metrics::sample_resources;
+use run_lock::{exclusive_run, RunLock};
+use runs::Runs;
+use utils::types::Error;
 
+mod cli;
+mod config;
 mod db;
 mod handlers;
 mod metrics;
 mod models;
+mod run_lock;
+mod runs;
 mod utils;
 
-async fn get_router() -> Router {
-    let database = init_databases().await.map_err(|e| e).unwrap();
+async fn get_router(config: Arc<Config>) -> Router {
+    let database = init_databases(&config).await.map_err(|e| e).unwrap();
 
     let shared_database = Arc::new(database);
+    let run_lock = Arc::new(RunLock::default());
 
-    Router::new()
-        .route("/", get(root))
+    // one benchmark at a time, a request that finds the lock taken is rejected before its
+    // resources are sampled
+    let benchmarks = Router::new()
         .route("/fetch-and-insert-data", get(fetch_and_insert_data))
         .route("/read-data", get(read_data))
+        .route("/point-lookup", get(point_lookup))
+        .route("/aggregate-data", get(aggregate_data))
+        .route("/update-data", get(update_data))
+        .route("/upsert-data", get(upsert_data))
+        .route("/index-experiment", post(index_experiment))
+        .route("/reset", post(reset))
+        .layer(middleware::from_fn_with_state(
+            config.monitoring.clone(),
+            sample_resources,
+        ))
+        .layer(middleware::from_fn_with_state(
+            run_lock.clone(),
+            exclusive_run,
+        ));
+
+    Router::new()
+        .route("/", get(root))
+        .merge(benchmarks)
+        .route("/runs", post(create_run))
+        .route("/runs/:id", get(get_run).delete(cancel_run))
+        .route("/runs/:id/events", get(run_events))
         .layer(Extension(shared_database))
+        .layer(Extension(Arc::new(Runs::new(run_lock))))
+        .layer(Extension(config))
 }
 
 #[tokio::main]
 async fn main() {
-    let app = get_router().await;
+    let cli = Cli::parse();
+
+    let config = match Config::load(cli.config.clone()) {
+        Ok(config) => config,
+        Err(Error::InvalidConfiguration(message)) => {
+            eprintln!("Invalid configuration:\n{}", message);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("{:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    match &cli.command {
+        None | Some(Command::Serve) => {}
+        // applies the pending Postgres migrations and exits without starting the server
+        Some(Command::Migrate) => {
+            if let Err(err) = PostgreSQL::init(
+                &config.postgres,
+                config.durability_of(config.postgres.durability),
+            )
+            .await
+            {
+                eprintln!("{:?}", err);
+                std::process::exit(1);
+            }
+
+            println!("Postgres migrations are up to date");
+            return;
+        }
+        Some(command) => {
+            if !cli::execute(&cli, command, &config).await {
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
+    let config = Arc::new(config);
+
+    let app = get_router(config.clone()).await;
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
+    let listener = tokio::net::TcpListener::bind(&config.server.bind_address)
+        .await
+        .unwrap();
 
     axum::serve(listener, app).await.unwrap();
 }
@@ -1 +1,4 @@
+pub mod latency_metrics;
 pub mod performance_metrics;
+pub mod resource_metrics;
+pub mod storage_metrics;
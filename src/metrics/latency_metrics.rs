@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use crate::utils::{
+    run_events::{emit, RunEvent},
+    write_metrics_into_file::record_metrics,
+};
+
+fn percentile(sorted_samples: &[Duration], percentile: f64) -> Duration {
+    // nearest-rank percentile over samples sorted in ascending order
+    let rank = ((percentile / 100.0) * sorted_samples.len() as f64).ceil() as usize;
+    sorted_samples[rank.clamp(1, sorted_samples.len()) - 1]
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+pub fn latency_metrics(mut samples: Vec<Duration>, message: &str) {
+    if samples.is_empty() {
+        println!("{} no samples recorded\n", message);
+        return;
+    }
+
+    samples.sort();
+
+    let total: Duration = samples.iter().sum();
+    let mean = total / samples.len() as u32;
+
+    let metrics_message = format!(
+        "{} p50 {} p90 {} p95 {} p99 {} max {} mean {}\n",
+        message,
+        format_duration(percentile(&samples, 50.0)),
+        format_duration(percentile(&samples, 90.0)),
+        format_duration(percentile(&samples, 95.0)),
+        format_duration(percentile(&samples, 99.0)),
+        format_duration(samples[samples.len() - 1]),
+        format_duration(mean),
+    );
+
+    record_metrics(metrics_message);
+
+    emit(RunEvent::OperationFinished {
+        message: message.trim_end_matches([' ', ':']).to_string(),
+        duration_ms: total.as_millis(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|ms| Duration::from_millis(*ms)).collect()
+    }
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let samples = millis(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+
+        assert_eq!(percentile(&samples, 50.0), Duration::from_millis(5));
+        assert_eq!(percentile(&samples, 90.0), Duration::from_millis(9));
+        assert_eq!(percentile(&samples, 95.0), Duration::from_millis(10));
+        assert_eq!(percentile(&samples, 100.0), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn percentile_stays_within_the_samples() {
+        let samples = millis(&[3, 7]);
+
+        assert_eq!(percentile(&samples, 0.0), Duration::from_millis(3));
+        assert_eq!(percentile(&samples, 1.0), Duration::from_millis(3));
+        assert_eq!(percentile(&samples, 99.0), Duration::from_millis(7));
+        assert_eq!(percentile(&millis(&[4]), 50.0), Duration::from_millis(4));
+    }
+}
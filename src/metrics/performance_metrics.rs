@@ -1,6 +1,9 @@
 use std::time::Instant;
 
-use crate::utils::write_metrics_into_file::write_metrics_into_file;
+use crate::utils::{
+    run_events::{emit, RunEvent},
+    write_metrics_into_file::record_metrics,
+};
 
 pub fn performance_metrics(start_time: Instant, end_time: Instant, message: &str) {
     let duration = end_time.duration_since(start_time);
@@ -12,7 +15,10 @@ pub fn performance_metrics(start_time: Instant, end_time: Instant, message: &str
 
     let metrics_message = format!("{} {}m {}s {}ms\n", message, minutes, seconds, milliseconds);
 
-    println!("{}", metrics_message);
+    record_metrics(metrics_message);
 
-    write_metrics_into_file(metrics_message);
+    emit(RunEvent::OperationFinished {
+        message: message.trim_end_matches([' ', ':']).to_string(),
+        duration_ms: duration.as_millis(),
+    });
 }
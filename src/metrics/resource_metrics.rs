@@ -0,0 +1,263 @@
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use tokio::{sync::oneshot, task::JoinHandle, time::interval};
+
+use crate::utils::{
+    run_events::error,
+    types::{Error, Result},
+    write_metrics_into_file::record_metrics,
+};
+
+// Linux reports CPU time in clock ticks, USER_HZ is 100 on every mainstream architecture.
+const CLOCK_TICKS_PER_SECOND: u64 = 100;
+
+// The `[monitoring]` section of the config file. Local database servers are only sampled when
+// their PIDs are set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitoringConfig {
+    pub sample_interval_ms: u64,
+    pub mongodb_pid: Option<u32>,
+    pub postgres_pid: Option<u32>,
+    pub surrealdb_pid: Option<u32>,
+}
+
+impl Default for MonitoringConfig {
+    fn default() -> Self {
+        MonitoringConfig {
+            sample_interval_ms: 100,
+            mongodb_pid: None,
+            postgres_pid: None,
+            surrealdb_pid: None,
+        }
+    }
+}
+
+impl MonitoringConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.sample_interval_ms == 0 {
+            errors.push("monitoring.sample_interval_ms must be positive".to_string());
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessSample {
+    pub elapsed: Duration,
+    pub cpu_time: Duration,
+    pub rss_bytes: u64,
+    // /proc/<pid>/io is only readable for our own processes
+    pub read_bytes: Option<u64>,
+    pub write_bytes: Option<u64>,
+}
+
+fn read_proc_file(pid: &str, file: &str) -> Result<String> {
+    fs::read_to_string(format!("/proc/{}/{}", pid, file)).map_err(|e| {
+        Error::OperationFailed(format!("Failed to read /proc/{}/{}: {}", pid, file, e))
+    })
+}
+
+fn cpu_time(pid: &str) -> Result<Duration> {
+    let stat = read_proc_file(pid, "stat")?;
+
+    // the command name can contain spaces, fields are counted from the closing parenthesis
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect())
+        .unwrap_or_default();
+
+    let ticks = |index: usize| -> Result<u64> {
+        fields
+            .get(index)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| {
+                Error::OperationFailed(format!("Unexpected format of /proc/{}/stat", pid))
+            })
+    };
+
+    // utime and stime are fields 14 and 15, the first field after the parenthesis is field 3
+    let total = ticks(11)? + ticks(12)?;
+    Ok(Duration::from_millis(total * 1000 / CLOCK_TICKS_PER_SECOND))
+}
+
+fn field_value(contents: &str, name: &str) -> Option<u64> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|v| v.parse().ok())
+}
+
+fn sample_process(pid: &str, started: Instant) -> Result<ProcessSample> {
+    let status = read_proc_file(pid, "status")?;
+    let rss_kb = field_value(&status, "VmRSS:").unwrap_or(0);
+
+    let io = read_proc_file(pid, "io").ok();
+
+    Ok(ProcessSample {
+        elapsed: started.elapsed(),
+        cpu_time: cpu_time(pid)?,
+        rss_bytes: rss_kb * 1024,
+        read_bytes: io.as_deref().and_then(|io| field_value(io, "read_bytes:")),
+        write_bytes: io.as_deref().and_then(|io| field_value(io, "write_bytes:")),
+    })
+}
+
+// Processes to sample: the benchmark itself plus the local database servers whose PIDs are
+// configured.
+fn monitored_processes(config: &MonitoringConfig) -> Vec<(String, String)> {
+    let mut processes = vec![("benchmark".to_string(), "self".to_string())];
+
+    for (name, pid) in [
+        ("MongoDB server", config.mongodb_pid),
+        ("Postgres server", config.postgres_pid),
+        ("SurrealDB server", config.surrealdb_pid),
+    ] {
+        if let Some(pid) = pid {
+            processes.push((name.to_string(), pid.to_string()));
+        }
+    }
+
+    processes
+}
+
+type ProcessSamples = Vec<(String, Vec<ProcessSample>)>;
+
+// Samples CPU time, RSS and I/O counters from /proc in the background until `finish` is called.
+pub struct ResourceSampler {
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<ProcessSamples>,
+}
+
+impl ResourceSampler {
+    pub fn start(config: &MonitoringConfig) -> Self {
+        let processes = monitored_processes(config);
+        let period = Duration::from_millis(config.sample_interval_ms);
+
+        let (stop, mut stopped) = oneshot::channel();
+        let handle = tokio::spawn(async move {
+            let started = Instant::now();
+            let mut samples: ProcessSamples = processes
+                .iter()
+                .map(|(name, _)| (name.clone(), Vec::new()))
+                .collect();
+            let mut ticker = interval(period);
+
+            loop {
+                let last = tokio::select! {
+                    _ = ticker.tick() => false,
+                    _ = &mut stopped => true,
+                };
+
+                for ((_, pid), (_, process_samples)) in processes.iter().zip(samples.iter_mut()) {
+                    // a server that went away is skipped instead of ending the sampling
+                    if let Ok(sample) = sample_process(pid, started) {
+                        process_samples.push(sample);
+                    }
+                }
+
+                if last {
+                    return samples;
+                }
+            }
+        });
+
+        ResourceSampler { stop, handle }
+    }
+
+    // Stops sampling and logs a summary per process.
+    pub async fn finish(self, message: &str) {
+        let _ = self.stop.send(());
+
+        match self.handle.await {
+            Ok(samples) => {
+                for (name, process_samples) in samples {
+                    resource_metrics(&process_samples, &format!("{} ({})", message, name));
+                }
+            }
+            Err(err) => {
+                error(format!(
+                    "Resource sampling for {} stopped without a summary: {}",
+                    message, err
+                ));
+            }
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.1}MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+fn counter_delta(first: Option<u64>, last: Option<u64>) -> String {
+    match (first, last) {
+        (Some(first), Some(last)) => format_bytes(last.saturating_sub(first)),
+        _ => "n/a".to_string(),
+    }
+}
+
+pub fn resource_metrics(samples: &[ProcessSample], message: &str) {
+    let (first, last) = match (samples.first(), samples.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            println!("{} no samples recorded\n", message);
+            return;
+        }
+    };
+
+    let cpu_time = last.cpu_time.saturating_sub(first.cpu_time);
+    let wall_time = last.elapsed.saturating_sub(first.elapsed);
+    let cpu_percent = if wall_time.is_zero() {
+        0.0
+    } else {
+        cpu_time.as_secs_f64() / wall_time.as_secs_f64() * 100.0
+    };
+
+    let peak_rss = samples.iter().map(|s| s.rss_bytes).max().unwrap_or(0);
+    let mean_rss = samples.iter().map(|s| s.rss_bytes).sum::<u64>() / samples.len() as u64;
+
+    let metrics_message = format!(
+        "{} cpu {:.3}s ({:.1}% of one core) rss peak {} mean {} read {} written {} ({} samples)\n",
+        message,
+        cpu_time.as_secs_f64(),
+        cpu_percent,
+        format_bytes(peak_rss),
+        format_bytes(mean_rss),
+        counter_delta(first.read_bytes, last.read_bytes),
+        counter_delta(first.write_bytes, last.write_bytes),
+        samples.len(),
+    );
+
+    record_metrics(metrics_message);
+}
+
+// Middleware that samples resource usage for the duration of every request, so each workload
+// is reported together with its CPU, memory and I/O cost.
+pub async fn sample_resources(
+    State(config): State<MonitoringConfig>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let message = format!(
+        "Resources used during {} {}",
+        request.method(),
+        request.uri().path()
+    );
+
+    let sampler = ResourceSampler::start(&config);
+
+    let response = next.run(request).await;
+
+    sampler.finish(&message).await;
+
+    response
+}
@@ -0,0 +1,17 @@
+use crate::utils::write_metrics_into_file::record_metrics;
+
+pub fn storage_metrics(bytes: Option<u64>, records: usize, message: &str) {
+    let metrics_message = match bytes {
+        Some(bytes) if records > 0 => format!(
+            "{} {} bytes ({:.1} bytes per record, {} records)\n",
+            message,
+            bytes,
+            bytes as f64 / records as f64,
+            records
+        ),
+        Some(bytes) => format!("{} {} bytes ({} records)\n", message, bytes, records),
+        None => format!("{} not available\n", message),
+    };
+
+    record_metrics(metrics_message);
+}
@@ -1,3 +1,4 @@
+pub mod aggregation_model;
 pub mod depth_history_model;
 pub mod rune_pool_history_model;
 pub mod swaps_history_model;
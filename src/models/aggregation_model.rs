@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::depth_history_model::DepthHistory,
+    utils::types::{Error, Result},
+};
+
+pub const SECONDS_PER_DAY: i64 = 86_400;
+pub const SECONDS_PER_WEEK: i64 = 604_800;
+
+// Relative tolerance used when comparing aggregates computed by different backends, the order
+// in which floats are summed differs between engines.
+const RELATIVE_TOLERANCE: f64 = 1e-9;
+
+// Days and weeks are bucketed as `floor(start_time / bucket_seconds)`, counted from the unix epoch.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DailyDepthAggregate {
+    pub day: i64,
+    pub avg_asset_price_usd: f64,
+    pub max_rune_depth: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WeeklyUnitsAggregate {
+    pub week: i64,
+    pub total_units: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DepthHistoryAggregates {
+    pub daily: Vec<DailyDepthAggregate>,
+    pub weekly: Vec<WeeklyUnitsAggregate>,
+}
+
+fn approx_eq(a: f64, b: f64) -> bool {
+    (a - b).abs() <= RELATIVE_TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+impl DepthHistoryAggregates {
+    // Computes the aggregates in Rust, used for the key-value stores which have no query engine.
+    // A weekly sum that overflows i64 fails, as `SUM(units)::BIGINT` does in Postgres.
+    pub fn fold<'a>(records: impl IntoIterator<Item = &'a DepthHistory>) -> Result<Self> {
+        let mut daily: BTreeMap<i64, (f64, usize, i64)> = BTreeMap::new();
+        let mut weekly: BTreeMap<i64, i64> = BTreeMap::new();
+
+        for record in records {
+            let day = record.start_time.div_euclid(SECONDS_PER_DAY);
+            let entry = daily.entry(day).or_insert((0.0, 0, i64::MIN));
+            entry.0 += record.asset_price_usd;
+            entry.1 += 1;
+            entry.2 = entry.2.max(record.rune_depth);
+
+            let week = record.start_time.div_euclid(SECONDS_PER_WEEK);
+            let total_units = weekly.entry(week).or_insert(0);
+            *total_units = total_units.checked_add(record.units).ok_or_else(|| {
+                Error::OperationFailed(format!("Total units of week {} overflow i64", week))
+            })?;
+        }
+
+        Ok(DepthHistoryAggregates {
+            daily: daily
+                .into_iter()
+                .map(
+                    |(day, (price_sum, count, max_rune_depth))| DailyDepthAggregate {
+                        day,
+                        avg_asset_price_usd: price_sum / count as f64,
+                        max_rune_depth,
+                    },
+                )
+                .collect(),
+            weekly: weekly
+                .into_iter()
+                .map(|(week, total_units)| WeeklyUnitsAggregate { week, total_units })
+                .collect(),
+        })
+    }
+
+    // Returns a description of every bucket that differs from `other`, empty when both agree.
+    pub fn differences(&self, other: &DepthHistoryAggregates) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        if self.daily.len() != other.daily.len() {
+            differences.push(format!(
+                "daily bucket count {} != {}",
+                self.daily.len(),
+                other.daily.len()
+            ));
+        }
+
+        for (a, b) in self.daily.iter().zip(other.daily.iter()) {
+            if a.day != b.day
+                || !approx_eq(a.avg_asset_price_usd, b.avg_asset_price_usd)
+                || a.max_rune_depth != b.max_rune_depth
+            {
+                differences.push(format!("daily {:?} != {:?}", a, b));
+            }
+        }
+
+        if self.weekly.len() != other.weekly.len() {
+            differences.push(format!(
+                "weekly bucket count {} != {}",
+                self.weekly.len(),
+                other.weekly.len()
+            ));
+        }
+
+        for (a, b) in self.weekly.iter().zip(other.weekly.iter()) {
+            if a.week != b.week || a.total_units != b.total_units {
+                differences.push(format!("weekly {:?} != {:?}", a, b));
+            }
+        }
+
+        differences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth(start_time: i64, asset_price_usd: f64, rune_depth: i64, units: i64) -> DepthHistory {
+        DepthHistory {
+            start_time,
+            end_time: start_time + 3600,
+            asset_depth: 1,
+            rune_depth,
+            asset_price: 0.5,
+            asset_price_usd,
+            liquidity_units: 3,
+            members_count: 4,
+            synth_units: 5,
+            synth_supply: 6,
+            units,
+            luvi: 0.25,
+        }
+    }
+
+    #[test]
+    fn fold_buckets_by_day_and_week() {
+        let records = [
+            depth(0, 1.0, 10, 1),
+            depth(3600, 3.0, 30, 2),
+            depth(SECONDS_PER_DAY, 5.0, 5, 4),
+            depth(SECONDS_PER_WEEK, 7.0, 7, 8),
+        ];
+        let aggregates = DepthHistoryAggregates::fold(&records).unwrap();
+
+        assert_eq!(
+            aggregates.daily,
+            vec![
+                DailyDepthAggregate {
+                    day: 0,
+                    avg_asset_price_usd: 2.0,
+                    max_rune_depth: 30,
+                },
+                DailyDepthAggregate {
+                    day: 1,
+                    avg_asset_price_usd: 5.0,
+                    max_rune_depth: 5,
+                },
+                DailyDepthAggregate {
+                    day: 7,
+                    avg_asset_price_usd: 7.0,
+                    max_rune_depth: 7,
+                },
+            ]
+        );
+        assert_eq!(
+            aggregates.weekly,
+            vec![
+                WeeklyUnitsAggregate {
+                    week: 0,
+                    total_units: 7,
+                },
+                WeeklyUnitsAggregate {
+                    week: 1,
+                    total_units: 8,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_floors_negative_start_times() {
+        let aggregates = DepthHistoryAggregates::fold(&[depth(-1, 1.0, 1, 1)]).unwrap();
+
+        assert_eq!(aggregates.daily[0].day, -1);
+        assert_eq!(aggregates.weekly[0].week, -1);
+    }
+
+    #[test]
+    fn fold_fails_when_weekly_units_overflow() {
+        let records = [depth(0, 1.0, 1, i64::MAX), depth(3600, 1.0, 1, 1)];
+
+        assert!(DepthHistoryAggregates::fold(&records).is_err());
+    }
+
+    #[test]
+    fn differences_tolerate_float_summation_order() {
+        let records = [depth(0, 0.1, 1, 1), depth(3600, 0.2, 1, 1)];
+        let aggregates = DepthHistoryAggregates::fold(&records).unwrap();
+        let mut other = aggregates.clone();
+        other.daily[0].avg_asset_price_usd += 1e-15;
+
+        assert!(aggregates.differences(&other).is_empty());
+
+        other.weekly[0].total_units += 1;
+        assert_eq!(aggregates.differences(&other).len(), 1);
+    }
+}
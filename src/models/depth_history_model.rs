@@ -2,34 +2,34 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
-use crate::utils::deserialize_util::deserialize_string_to_number;
+use crate::utils::deserialize_util::{deserialize_string_to_integer, deserialize_string_to_number};
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DepthHistory {
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub start_time: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub end_time: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub asset_depth: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub rune_depth: f64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub start_time: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub end_time: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub asset_depth: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub rune_depth: i64,
     #[serde(deserialize_with = "deserialize_string_to_number")]
     pub asset_price: f64,
     #[serde(deserialize_with = "deserialize_string_to_number")]
     #[serde(rename = "assetPriceUSD")]
     pub asset_price_usd: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub liquidity_units: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub members_count: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub synth_units: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub synth_supply: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub units: f64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub liquidity_units: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub members_count: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub synth_units: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub synth_supply: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub units: i64,
     #[serde(deserialize_with = "deserialize_string_to_number")]
     pub luvi: f64,
 }
@@ -1,19 +1,19 @@
 use std::collections::HashSet;
 
-use crate::utils::deserialize_util::deserialize_string_to_number;
+use crate::utils::deserialize_util::{deserialize_string_to_integer, deserialize_string_to_number};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RunePoolHistory {
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub start_time: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub end_time: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub count: f64,
-    #[serde(deserialize_with = "deserialize_string_to_number")]
-    pub units: f64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub start_time: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub end_time: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub count: i64,
+    #[serde(deserialize_with = "deserialize_string_to_integer")]
+    pub units: i64,
 }
 
 impl RunePoolHistory {
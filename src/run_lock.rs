@@ -0,0 +1,89 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use reqwest::StatusCode;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+// Benchmarks write to the same tables and `_keys_index` and time themselves, so two running at
+// once corrupt each other's measurements. Only one holds the lock at a time: route requests are
+// rejected while it is taken, background runs queue for it.
+#[derive(Default)]
+pub struct RunLock {
+    lock: Arc<AsyncMutex<()>>,
+    // what holds the lock and since when, for the rejection message
+    holder: Mutex<Option<(String, Instant)>>,
+}
+
+// Releases the lock when dropped.
+pub struct RunGuard {
+    _guard: OwnedMutexGuard<()>,
+    run_lock: Arc<RunLock>,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        if let Ok(mut holder) = self.run_lock.holder.lock() {
+            *holder = None;
+        }
+    }
+}
+
+impl RunLock {
+    fn hold(self: &Arc<Self>, guard: OwnedMutexGuard<()>, holder: String) -> RunGuard {
+        if let Ok(mut current) = self.holder.lock() {
+            *current = Some((holder, Instant::now()));
+        }
+
+        RunGuard {
+            _guard: guard,
+            run_lock: self.clone(),
+        }
+    }
+
+    // Waits until every earlier benchmark finished.
+    pub async fn acquire(self: &Arc<Self>, holder: String) -> RunGuard {
+        let guard = self.lock.clone().lock_owned().await;
+        self.hold(guard, holder)
+    }
+
+    pub fn try_acquire(self: &Arc<Self>, holder: String) -> Option<RunGuard> {
+        let guard = self.lock.clone().try_lock_owned().ok()?;
+        Some(self.hold(guard, holder))
+    }
+
+    pub fn holder(&self) -> Option<String> {
+        let holder = self.holder.lock().ok()?;
+        holder.as_ref().map(|(holder, since)| {
+            format!("{} (running for {}s)", holder, since.elapsed().as_secs())
+        })
+    }
+}
+
+// Runs the request only when no other benchmark is running, otherwise answers 409 Conflict
+// with what is running.
+pub async fn exclusive_run(
+    State(run_lock): State<Arc<RunLock>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let holder = format!("{} {}", request.method(), request.uri().path());
+
+    match run_lock.try_acquire(holder) {
+        Some(_guard) => next.run(request).await,
+        None => (
+            StatusCode::CONFLICT,
+            format!(
+                "Another benchmark is running: {}, retry once it finished",
+                run_lock.holder().unwrap_or_else(|| "unknown".to_string())
+            ),
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tokio::task::AbortHandle;
+
+use crate::{
+    config::Config,
+    db::{Backend, DataBases},
+    handlers::{
+        aggregate_data::aggregate, fetch_and_insert_data::ingest, index_experiment::experiment,
+        point_lookup::lookup, read_data::read, update_data::update, upsert_data::upsert,
+        BackendResult, Outcome, Report,
+    },
+    metrics::resource_metrics::ResourceSampler,
+    run_lock::RunLock,
+    utils::{
+        key_sampler::KeyDistribution,
+        midgard_api::Dataset,
+        run_events::{RunEvent, RunEvents},
+        write_metrics_into_file::MetricsSink,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Workload {
+    Read,
+    PointLookup,
+    Aggregate,
+    Update,
+    Upsert,
+    IndexExperiment,
+}
+
+impl Workload {
+    // The operation its backend results are recorded under.
+    fn operation(&self) -> &'static str {
+        match self {
+            Workload::Read => "read",
+            Workload::PointLookup => "point lookup",
+            Workload::Aggregate => "aggregate",
+            Workload::Update => "update",
+            Workload::Upsert => "upsert",
+            Workload::IndexExperiment => "index experiment",
+        }
+    }
+}
+
+pub const DEFAULT_WORKLOADS: [Workload; 3] =
+    [Workload::Read, Workload::PointLookup, Workload::Aggregate];
+
+// What a benchmark run does: an ingest followed by the workloads. The body of `POST /runs` and
+// the flags of the `run` command. Empty backends and datasets mean all of them, unset workload
+// parameters fall back to the `[workloads]` config.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunRequest {
+    pub backends: Vec<Backend>,
+    pub datasets: Vec<Dataset>,
+    pub reset: bool,
+    pub transactional: bool,
+    pub cold: bool,
+    pub reconnect: bool,
+    pub workloads: Vec<Workload>,
+    pub count: Option<usize>,
+    pub distribution: Option<KeyDistribution>,
+    pub factor: Option<f64>,
+}
+
+impl Default for RunRequest {
+    fn default() -> Self {
+        RunRequest {
+            backends: vec![],
+            datasets: vec![],
+            reset: false,
+            transactional: false,
+            cold: false,
+            reconnect: false,
+            workloads: DEFAULT_WORKLOADS.to_vec(),
+            count: None,
+            distribution: None,
+            factor: None,
+        }
+    }
+}
+
+pub fn selected<T: Copy>(selection: &[T], all: &[T]) -> Vec<T> {
+    if selection.is_empty() {
+        all.to_vec()
+    } else {
+        selection.to_vec()
+    }
+}
+
+// Runs the ingest and then every workload in order. Backends that fail are recorded and the run
+// goes on. A workload that could not start, e.g. an upsert that cannot fetch from Midgard, is
+// recorded as failed on every selected backend and the run goes on with the next one. Only an
+// ingest that could not start stops the run.
+pub async fn run_benchmark(database: &DataBases, config: &Config, request: &RunRequest) -> Outcome {
+    let backends = selected(&request.backends, &Backend::ALL);
+    let datasets = selected(&request.datasets, &Dataset::ALL);
+    let mut report = Report::new("Completed run");
+
+    report.extend(
+        ingest(
+            database,
+            config,
+            &backends,
+            &datasets,
+            request.reset,
+            request.transactional,
+        )
+        .await?,
+    );
+
+    for workload in &request.workloads {
+        let outcome = match workload {
+            Workload::Read => {
+                read(database, config, &backends, request.cold, request.reconnect).await
+            }
+            Workload::PointLookup => {
+                lookup(
+                    database,
+                    &backends,
+                    request.count.unwrap_or(config.workloads.point_lookup_count),
+                    request
+                        .distribution
+                        .unwrap_or(config.workloads.key_distribution),
+                )
+                .await
+            }
+            Workload::Aggregate => aggregate(database, &backends).await,
+            Workload::Update => {
+                update(
+                    database,
+                    &backends,
+                    request
+                        .factor
+                        .unwrap_or(config.workloads.update_price_factor),
+                )
+                .await
+            }
+            Workload::Upsert => upsert(database, config, &backends).await,
+            Workload::IndexExperiment => experiment(database, config, &backends).await,
+        };
+
+        match outcome {
+            Ok(workload_report) => report.extend(workload_report),
+            Err((_, message)) => report.not_started(&backends, workload.operation(), message),
+        }
+    }
+
+    Ok(report)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    // waiting for the benchmark before it to finish
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+pub enum Cancellation {
+    Requested,
+    AlreadyFinished,
+    UnknownRun,
+}
+
+struct RunState {
+    request: RunRequest,
+    status: RunStatus,
+    message: Option<String>,
+    started: Instant,
+    finished: Option<Instant>,
+    // per-backend metrics lines, appended as each operation completes
+    metrics: MetricsSink,
+    events: Arc<RunEvents>,
+    job: AbortHandle,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    pub id: u64,
+    pub status: RunStatus,
+    pub message: Option<String>,
+    pub elapsed_ms: u128,
+    pub request: RunRequest,
+    // per backend and operation, appended as each operation finishes
+    pub results: Vec<BackendResult>,
+    pub metrics: Vec<String>,
+}
+
+// Benchmark runs started over HTTP. They run in the background and are kept for the lifetime
+// of the server, so their results can be fetched after the request that started them returned.
+pub struct Runs {
+    next_id: AtomicU64,
+    runs: Mutex<HashMap<u64, RunState>>,
+    run_lock: Arc<RunLock>,
+}
+
+impl Runs {
+    pub fn new(run_lock: Arc<RunLock>) -> Self {
+        Runs {
+            next_id: AtomicU64::new(0),
+            runs: Mutex::new(HashMap::new()),
+            run_lock,
+        }
+    }
+
+    pub fn start(
+        self: &Arc<Self>,
+        database: Arc<DataBases>,
+        config: Arc<Config>,
+        request: RunRequest,
+    ) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let metrics = MetricsSink::default();
+        let events = Arc::new(RunEvents::default());
+
+        // the runs stay locked until the state is in, so a job that gets the run lock right
+        // away finds its run when it marks it running
+        let Ok(mut runs) = self.runs.lock() else {
+            return id;
+        };
+
+        let job = {
+            let request = request.clone();
+            let runs = self.clone();
+            tokio::spawn(events.clone().scope(metrics.clone().collect(async move {
+                let _guard = runs.run_lock.acquire(format!("run {}", id)).await;
+                runs.set_running(id);
+
+                let sampler = ResourceSampler::start(&config.monitoring);
+                let outcome = run_benchmark(&database, &config, &request).await;
+                sampler
+                    .finish(&format!("Resources used during run {}", id))
+                    .await;
+                outcome
+            })))
+        };
+
+        runs.insert(
+            id,
+            RunState {
+                request,
+                status: RunStatus::Queued,
+                message: None,
+                started: Instant::now(),
+                finished: None,
+                metrics,
+                events,
+                job: job.abort_handle(),
+            },
+        );
+        drop(runs);
+
+        // a cancelled or panicking job still finishes the run instead of leaving it running
+        let runs = self.clone();
+        tokio::spawn(async move {
+            let (status, message) = match job.await {
+                Ok(Ok(report)) => {
+                    let status = match report.failures() {
+                        0 => RunStatus::Succeeded,
+                        _ => RunStatus::Failed,
+                    };
+                    (status, report.summary())
+                }
+                Ok(Err((_, message))) => (RunStatus::Failed, message.to_string()),
+                Err(err) if err.is_cancelled() => {
+                    (RunStatus::Cancelled, "Run cancelled".to_string())
+                }
+                // the panic ends up in the run's message and its `run-finished` event
+                Err(err) => (RunStatus::Failed, format!("Run panicked: {}", err)),
+            };
+            runs.finish(id, status, message);
+        });
+
+        id
+    }
+
+    fn set_running(&self, id: u64) {
+        if let Ok(mut runs) = self.runs.lock() {
+            if let Some(run) = runs.get_mut(&id) {
+                run.status = RunStatus::Running;
+                run.events.send(RunEvent::RunStarted);
+            }
+        }
+    }
+
+    fn finish(&self, id: u64, status: RunStatus, message: String) {
+        if let Ok(mut runs) = self.runs.lock() {
+            if let Some(run) = runs.get_mut(&id) {
+                run.status = status;
+                run.message = Some(message.clone());
+                run.finished = Some(Instant::now());
+                run.events.send(RunEvent::RunFinished { status, message });
+            }
+        }
+    }
+
+    // Stops the job at its next await point. The run turns `cancelled` once the job stopped,
+    // an operation a backend is busy with is not rolled back.
+    pub fn cancel(&self, id: u64) -> Cancellation {
+        let Ok(runs) = self.runs.lock() else {
+            return Cancellation::UnknownRun;
+        };
+
+        match runs.get(&id) {
+            Some(run) if matches!(run.status, RunStatus::Queued | RunStatus::Running) => {
+                run.job.abort();
+                Cancellation::Requested
+            }
+            Some(_) => Cancellation::AlreadyFinished,
+            None => Cancellation::UnknownRun,
+        }
+    }
+
+    pub fn events(&self, id: u64) -> Option<Arc<RunEvents>> {
+        let runs = self.runs.lock().ok()?;
+        runs.get(&id).map(|run| run.events.clone())
+    }
+
+    pub fn report(&self, id: u64) -> Option<RunReport> {
+        let runs = self.runs.lock().ok()?;
+        let run = runs.get(&id)?;
+
+        Some(RunReport {
+            id,
+            status: run.status,
+            message: match run.status {
+                RunStatus::Queued => self
+                    .run_lock
+                    .holder()
+                    .map(|holder| format!("Waiting for {}", holder)),
+                _ => run.message.clone(),
+            },
+            elapsed_ms: run
+                .finished
+                .unwrap_or_else(Instant::now)
+                .duration_since(run.started)
+                .as_millis(),
+            request: run.request.clone(),
+            results: run.events.results(),
+            metrics: run.metrics.lines(),
+        })
+    }
+}
@@ -1,3 +1,10 @@
 pub mod deserialize_util;
+pub mod disk_usage;
+pub mod durability;
+pub mod key_sampler;
+pub mod kv_keys;
+pub mod midgard_api;
+pub mod run_events;
 pub mod types;
+pub mod verification;
 pub mod write_metrics_into_file;
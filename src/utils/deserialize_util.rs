@@ -25,3 +25,68 @@ where
         _ => Err(serde::de::Error::custom("Expected a string or number")),
     }
 }
+
+// Custom function to handle deserialization from string to i64 without going through f64,
+// Midgard encodes depths, units and supplies as strings that can exceed f64's 53-bit mantissa
+pub fn deserialize_string_to_integer<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value: Value = Value::deserialize(deserializer)?;
+
+    match value {
+        // If it's a string, parse it as an integer, failing instead of rounding
+        Value::String(s) => s.parse::<i64>().map_err(serde::de::Error::custom),
+        // Data written before the integer columns stored every field as a double, so an integral
+        // float like 1734454800.0 is accepted as well, fractions and out-of-range values are not
+        Value::Number(num) => num
+            .as_i64()
+            .or_else(|| num.as_f64().and_then(integral_f64_to_i64))
+            .ok_or_else(|| serde::de::Error::custom("Expected an integer that fits in i64")),
+        _ => Err(serde::de::Error::custom("Expected a string or number")),
+    }
+}
+
+fn integral_f64_to_i64(f: f64) -> Option<i64> {
+    // i64::MAX is not representable as f64, 2^63 is the first value out of range
+    if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+        Some(f as i64)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Integer(#[serde(deserialize_with = "deserialize_string_to_integer")] i64);
+
+    fn integer(json: &str) -> serde_json::Result<i64> {
+        serde_json::from_str::<Integer>(json).map(|integer| integer.0)
+    }
+
+    #[test]
+    fn integers_are_read_from_strings_and_numbers() {
+        assert_eq!(
+            integer(r#""9007199254740993""#).unwrap(),
+            9_007_199_254_740_993
+        );
+        assert_eq!(integer("-42").unwrap(), -42);
+    }
+
+    #[test]
+    fn integral_floats_from_stored_doubles_are_accepted() {
+        assert_eq!(integer("1734454800.0").unwrap(), 1_734_454_800);
+        assert_eq!(integer("54734009130230.0").unwrap(), 54_734_009_130_230);
+        assert_eq!(integer("-3.0").unwrap(), -3);
+    }
+
+    #[test]
+    fn fractions_and_out_of_range_floats_are_rejected() {
+        assert!(integer("1.5").is_err());
+        assert!(integer("9223372036854775808.0").is_err());
+        assert!(integer(r#""1734454800.0""#).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+use std::{fs, path::Path};
+
+use crate::utils::types::{Error, Result};
+
+// Total size in bytes of every file below `path`.
+pub fn directory_size(path: impl AsRef<Path>) -> Result<u64> {
+    let path = path.as_ref();
+    let entries = fs::read_dir(path).map_err(|e| {
+        Error::OperationFailed(format!("Failed to read directory {:?}: {}", path, e))
+    })?;
+
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry.map_err(|e| {
+            Error::OperationFailed(format!("Failed to read entry in {:?}: {}", path, e))
+        })?;
+        let metadata = entry.metadata().map_err(|e| {
+            Error::OperationFailed(format!(
+                "Failed to read metadata of {:?}: {}",
+                entry.path(),
+                e
+            ))
+        })?;
+
+        if metadata.is_dir() {
+            size += directory_size(entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+
+    Ok(size)
+}
@@ -0,0 +1,36 @@
+use serde::Deserialize;
+
+// Write durability the backends are opened with, `durability` in the config file or DURABILITY,
+// overridden per backend by the `durability` key of its section.
+//
+// | level      | RocksDB       | LevelDB       | MongoDB          | Postgres                  |
+// |------------|---------------|---------------|------------------|---------------------------|
+// | `default`  | WAL, no fsync | log, no fsync | server default   | server default            |
+// | `off`      | WAL disabled  | rejected      | w=0              | rejected                  |
+// | `buffered` | WAL, no fsync | log, no fsync | w=1, journal off | synchronous_commit = off  |
+// | `sync`     | WAL, fsync    | log, fsync    | w=1, journal on  | synchronous_commit = on * |
+//
+// LevelDB always writes its log and Postgres can only skip the WAL for unlogged tables, so
+// neither runs with `off`. For RocksDB and LevelDB `default` is their own buffered mode.
+// * The Postgres server default is usually `synchronous_commit = on` as well, so `sync` also
+// refuses a server running with `fsync = off`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    #[default]
+    Default,
+    Off,
+    Buffered,
+    Sync,
+}
+
+impl Durability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Durability::Default => "default",
+            Durability::Off => "off",
+            Durability::Buffered => "buffered",
+            Durability::Sync => "sync",
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use clap::ValueEnum;
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use rand_distr::Zipf;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::types::{Error, Result};
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyDistribution {
+    #[default]
+    Uniform,
+    Zipfian,
+}
+
+impl KeyDistribution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyDistribution::Uniform => "uniform",
+            KeyDistribution::Zipfian => "zipfian",
+        }
+    }
+}
+
+// Exponent of the Zipfian distribution, 0.99 is the YCSB default.
+const ZIPF_EXPONENT: f64 = 0.99;
+
+// Picks `count` keys from `keys` (with repetition). For the Zipfian distribution the most
+// recent start times are the hottest ones, which mirrors how our API is queried.
+pub fn sample_keys(keys: &[i64], count: usize, distribution: KeyDistribution) -> Result<Vec<i64>> {
+    if keys.is_empty() {
+        return Err(Error::OperationFailed(
+            "No stored keys to sample lookups from.".to_string(),
+        ));
+    }
+
+    let mut rng = thread_rng();
+
+    match distribution {
+        KeyDistribution::Uniform => Ok((0..count)
+            .map(|_| *keys.choose(&mut rng).unwrap())
+            .collect()),
+        KeyDistribution::Zipfian => {
+            let mut ranked_keys = keys.to_vec();
+            ranked_keys.sort_by(|a, b| b.cmp(a));
+
+            let zipf = Zipf::new(ranked_keys.len() as u64, ZIPF_EXPONENT).map_err(|e| {
+                Error::OperationFailed(format!("Failed to build Zipfian distribution: {:?}", e))
+            })?;
+
+            Ok((0..count)
+                .map(|_| {
+                    let rank = rng.sample(zipf) as usize;
+                    ranked_keys[rank.clamp(1, ranked_keys.len()) - 1]
+                })
+                .collect())
+        }
+    }
+}
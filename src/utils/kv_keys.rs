@@ -0,0 +1,232 @@
+use crate::models::{depth_history_model::DepthHistory, rune_pool_history_model::RunePoolHistory};
+
+// Keys in the key-value stores are prefixed with the dataset name, otherwise depth history and
+// rune pool history records with the same start time overwrite each other.
+pub const DEPTH_HISTORY_PREFIX: &[u8] = b"depth_history:";
+pub const RUNE_POOL_HISTORY_PREFIX: &[u8] = b"rune_pool_history:";
+
+fn prefixed_key(prefix: &[u8], start_time: i64) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.extend_from_slice(start_time.to_string().as_bytes());
+    key
+}
+
+pub fn depth_history_key(start_time: i64) -> Vec<u8> {
+    prefixed_key(DEPTH_HISTORY_PREFIX, start_time)
+}
+
+pub fn rune_pool_history_key(start_time: i64) -> Vec<u8> {
+    prefixed_key(RUNE_POOL_HISTORY_PREFIX, start_time)
+}
+
+// The start time a depth history key was built from, None for any other key.
+pub fn depth_history_start_time(key: &[u8]) -> Option<i64> {
+    let start_time = key.strip_prefix(DEPTH_HISTORY_PREFIX)?;
+    std::str::from_utf8(start_time).ok()?.parse().ok()
+}
+
+// Before the dataset prefixes, records were keyed by their JSON encoded start time alone, so
+// depth history and rune pool history overwrote each other, and the index experiment wrote its
+// entries under `depth_history_idx:`. The stores move such records to their prefixed key when
+// they open and drop the old index entries.
+pub const LEGACY_INDEX_PREFIX: &[u8] = b"depth_history_idx:";
+
+// The start time was a float then, so the keys carry a `.0` suffix, e.g. `1734454800.0`.
+pub fn is_legacy_record_key(key: &[u8]) -> bool {
+    let key = key.strip_suffix(b".0").unwrap_or(key);
+    let digits = key.strip_prefix(b"-").unwrap_or(key);
+    !digits.is_empty() && digits.iter().all(u8::is_ascii_digit)
+}
+
+pub fn is_legacy_key(key: &[u8]) -> bool {
+    is_legacy_record_key(key) || key.starts_with(LEGACY_INDEX_PREFIX)
+}
+
+// The prefixed key of a legacy record, by the dataset its value decodes as. Depth history is
+// tried first, its records also decode as rune pool history. None when it is neither. Legacy
+// values hold their integers as floats, the integer fields accept those when they are integral.
+pub fn migrated_key(val: &[u8]) -> Option<Vec<u8>> {
+    if let Ok(record) = serde_json::from_slice::<DepthHistory>(val) {
+        return Some(depth_history_key(record.start_time));
+    }
+
+    serde_json::from_slice::<RunePoolHistory>(val)
+        .ok()
+        .map(|record| rune_pool_history_key(record.start_time))
+}
+
+// The index experiment keeps its copy of the depth history and its index entries under their
+// own prefix, so it can be cleared without touching the benchmark data.
+pub const INDEX_EXPERIMENT_PREFIX: &[u8] = b"index_experiment:";
+pub const INDEX_EXPERIMENT_RECORD_PREFIX: &[u8] = b"index_experiment:depth_history:";
+
+pub fn index_experiment_key(start_time: i64) -> Vec<u8> {
+    prefixed_key(INDEX_EXPERIMENT_RECORD_PREFIX, start_time)
+}
+
+// Manual secondary index entries map an indexed value to the primary key of the record. Values
+// are zero padded so that the lexicographic key order matches the numeric order.
+pub const DEPTH_HISTORY_END_TIME_INDEX_PREFIX: &[u8] = b"index_experiment:idx:end_time:";
+pub const DEPTH_HISTORY_PRICE_INDEX_PREFIX: &[u8] = b"index_experiment:idx:asset_price_usd:";
+
+fn end_time_index_value(end_time: i64) -> String {
+    format!("{:020}", end_time)
+}
+
+fn price_index_value(asset_price_usd: f64) -> String {
+    format!("{:025.8}", asset_price_usd)
+}
+
+pub fn depth_history_end_time_index_key(end_time: i64, start_time: i64) -> Vec<u8> {
+    let mut key = DEPTH_HISTORY_END_TIME_INDEX_PREFIX.to_vec();
+    key.extend_from_slice(format!("{}:{}", end_time_index_value(end_time), start_time).as_bytes());
+    key
+}
+
+pub fn depth_history_price_index_key(asset_price_usd: f64, start_time: i64) -> Vec<u8> {
+    let mut key = DEPTH_HISTORY_PRICE_INDEX_PREFIX.to_vec();
+    key.extend_from_slice(
+        format!("{}:{}", price_index_value(asset_price_usd), start_time).as_bytes(),
+    );
+    key
+}
+
+// Smallest key greater than every key starting with `prefix`.
+pub fn prefix_upper_bound(prefix: &[u8]) -> Vec<u8> {
+    let mut bound = prefix.to_vec();
+    if let Some(last) = bound.last_mut() {
+        *last += 1;
+    }
+    bound
+}
+
+// Key range [from, to) covering index entries with from_end_time <= end_time <= to_end_time.
+pub fn depth_history_end_time_index_range(
+    from_end_time: i64,
+    to_end_time: i64,
+) -> (Vec<u8>, Vec<u8>) {
+    let mut from = DEPTH_HISTORY_END_TIME_INDEX_PREFIX.to_vec();
+    from.extend_from_slice(end_time_index_value(from_end_time).as_bytes());
+
+    let mut to = DEPTH_HISTORY_END_TIME_INDEX_PREFIX.to_vec();
+    to.extend_from_slice(end_time_index_value(to_end_time + 1).as_bytes());
+
+    (from, to)
+}
+
+// Key range [from, to) covering index entries with asset_price_usd >= min_asset_price_usd.
+pub fn depth_history_price_index_range(min_asset_price_usd: f64) -> (Vec<u8>, Vec<u8>) {
+    let mut from = DEPTH_HISTORY_PRICE_INDEX_PREFIX.to_vec();
+    from.extend_from_slice(price_index_value(min_asset_price_usd).as_bytes());
+
+    (from, prefix_upper_bound(DEPTH_HISTORY_PRICE_INDEX_PREFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_range(key: &[u8], (from, to): &(Vec<u8>, Vec<u8>)) -> bool {
+        key >= from.as_slice() && key < to.as_slice()
+    }
+
+    #[test]
+    fn dataset_prefixes_keep_records_apart() {
+        assert_eq!(depth_history_key(1700000000), b"depth_history:1700000000");
+        assert_eq!(
+            rune_pool_history_key(1700000000),
+            b"rune_pool_history:1700000000"
+        );
+        assert!(index_experiment_key(1700000000).starts_with(INDEX_EXPERIMENT_PREFIX));
+        assert!(depth_history_end_time_index_key(1, 1).starts_with(INDEX_EXPERIMENT_PREFIX));
+        assert!(depth_history_price_index_key(1.0, 1).starts_with(INDEX_EXPERIMENT_PREFIX));
+    }
+
+    #[test]
+    fn legacy_keys_are_recognised() {
+        assert!(is_legacy_record_key(b"1700000000"));
+        assert!(is_legacy_record_key(b"-5"));
+        assert!(is_legacy_record_key(b"1734454800.0"));
+        assert!(!is_legacy_record_key(b"1734454800.5"));
+        assert!(!is_legacy_record_key(b".0"));
+        assert!(!is_legacy_record_key(b""));
+        assert!(!is_legacy_record_key(b"-"));
+        assert!(!is_legacy_record_key(b"_keys_index"));
+        assert!(!is_legacy_record_key(&depth_history_key(1700000000)));
+        assert!(is_legacy_key(b"depth_history_idx:end_time:1:1"));
+        assert!(!is_legacy_key(&depth_history_end_time_index_key(1, 1)));
+    }
+
+    #[test]
+    fn legacy_records_move_to_their_dataset() {
+        let rune_pool = br#"{"startTime":10,"endTime":20,"count":1,"units":2}"#;
+        assert_eq!(migrated_key(rune_pool), Some(rune_pool_history_key(10)));
+
+        let depth = br#"{"startTime":10,"endTime":20,"assetDepth":1,"runeDepth":2,"assetPrice":0.5,"assetPriceUSD":1.5,"liquidityUnits":3,"membersCount":4,"synthUnits":5,"synthSupply":6,"units":7,"luvi":0.25}"#;
+        assert_eq!(migrated_key(depth), Some(depth_history_key(10)));
+
+        assert_eq!(migrated_key(b"not a record"), None);
+    }
+
+    #[test]
+    fn baseline_records_are_migrated() {
+        // as found in data/leveldb, written before the dataset prefixes
+        let key = b"1734454800.0";
+        let val = br#"{"startTime":1734454800.0,"endTime":1734458400.0,"count":1.0,"units":54734009130230.0}"#;
+
+        assert!(is_legacy_key(key));
+        assert_eq!(migrated_key(val), Some(rune_pool_history_key(1734454800)));
+    }
+
+    #[test]
+    fn depth_history_keys_give_back_their_start_time() {
+        assert_eq!(depth_history_start_time(&depth_history_key(-7)), Some(-7));
+        assert_eq!(depth_history_start_time(&rune_pool_history_key(7)), None);
+        assert_eq!(depth_history_start_time(&index_experiment_key(7)), None);
+    }
+
+    #[test]
+    fn padding_keeps_numeric_order() {
+        assert!(
+            depth_history_end_time_index_key(999, 1) < depth_history_end_time_index_key(1000, 1)
+        );
+        assert!(depth_history_price_index_key(9.5, 1) < depth_history_price_index_key(10.25, 1));
+        assert!(depth_history_price_index_key(0.1, 1) < depth_history_price_index_key(0.2, 1));
+    }
+
+    #[test]
+    fn prefix_upper_bound_follows_every_prefixed_key() {
+        let bound = prefix_upper_bound(b"abc:");
+        assert_eq!(bound, b"abc;");
+        assert!(b"abc:\xff\xff".as_slice() < bound.as_slice());
+        assert!(b"abd".as_slice() > bound.as_slice());
+    }
+
+    #[test]
+    fn end_time_range_is_inclusive() {
+        let range = depth_history_end_time_index_range(100, 200);
+
+        assert!(!in_range(&depth_history_end_time_index_key(99, 1), &range));
+        assert!(in_range(&depth_history_end_time_index_key(100, 1), &range));
+        assert!(in_range(&depth_history_end_time_index_key(150, 1), &range));
+        assert!(in_range(
+            &depth_history_end_time_index_key(200, i64::MAX),
+            &range
+        ));
+        assert!(!in_range(&depth_history_end_time_index_key(201, 1), &range));
+        assert!(!in_range(&depth_history_price_index_key(150.0, 1), &range));
+    }
+
+    #[test]
+    fn price_range_covers_prices_from_the_minimum() {
+        let range = depth_history_price_index_range(2.5);
+
+        assert!(!in_range(
+            &depth_history_price_index_key(2.49999999, 1),
+            &range
+        ));
+        assert!(in_range(&depth_history_price_index_key(2.5, 1), &range));
+        assert!(in_range(&depth_history_price_index_key(1e12, 1), &range));
+        assert!(!in_range(&depth_history_end_time_index_key(3, 1), &range));
+    }
+}
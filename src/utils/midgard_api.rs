@@ -0,0 +1,389 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+    sync::Mutex,
+    time::{sleep_until, Instant},
+};
+
+use crate::{
+    models::{
+        depth_history_model::DepthHistoryResponse, rune_pool_history_model::RunePoolHistoryResponse,
+    },
+    utils::{
+        run_events::error,
+        types::{Error, Result},
+    },
+};
+
+// bytes of an error response body kept in the error message
+const ERROR_BODY_LIMIT: usize = 200;
+// slowest accepted rate limit, one request every MAX_REQUEST_INTERVAL
+const MIN_REQUESTS_PER_SECOND: f64 = 0.001;
+const MAX_REQUEST_INTERVAL: Duration = Duration::from_secs(1000);
+
+// Midgard datasets an ingest can be limited to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dataset {
+    DepthHistory,
+    RunePoolHistory,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 2] = [Dataset::DepthHistory, Dataset::RunePoolHistory];
+}
+
+// How the Midgard API is called. Public Midgard nodes throttle, so requests are spaced out and
+// the ones answered with 429 or a 5xx are retried with exponential backoff.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MidgardConfig {
+    // per attempt, including reading the body
+    pub timeout_secs: u64,
+    // attempts after the first one
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    // 0 sends requests without spacing them out
+    pub requests_per_second: f64,
+}
+
+impl Default for MidgardConfig {
+    fn default() -> Self {
+        MidgardConfig {
+            timeout_secs: 30,
+            max_retries: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            requests_per_second: 5.0,
+        }
+    }
+}
+
+impl MidgardConfig {
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        if self.timeout_secs == 0 {
+            errors.push("midgard.timeout_secs must be positive".to_string());
+        }
+        if self.initial_backoff_ms == 0 {
+            errors.push("midgard.initial_backoff_ms must be positive".to_string());
+        }
+        if self.max_backoff_ms < self.initial_backoff_ms {
+            errors.push(
+                "midgard.max_backoff_ms must be at least midgard.initial_backoff_ms".to_string(),
+            );
+        }
+        if !self.requests_per_second.is_finite()
+            || self.requests_per_second < 0.0
+            || (self.requests_per_second > 0.0
+                && self.requests_per_second < MIN_REQUESTS_PER_SECOND)
+        {
+            errors.push(format!(
+                "midgard.requests_per_second must be 0 or at least {}",
+                MIN_REQUESTS_PER_SECOND
+            ));
+        }
+    }
+}
+
+// One client for every Midgard request, shared through `DataBases` so its connection pool and
+// rate limit cover all routes and runs of the process.
+pub struct MidgardClient {
+    client: Client,
+    config: MidgardConfig,
+    // earliest time the next request may be sent
+    next_request: Mutex<Instant>,
+}
+
+// Whether an attempt is worth repeating, and how long the server asked us to wait.
+enum Failure {
+    Retryable(String, Option<Duration>),
+    Fatal(String),
+}
+
+impl MidgardClient {
+    pub fn new(config: &MidgardConfig) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| {
+                Error::OperationFailed(format!("Failed to build the Midgard client: {}", e))
+            })?;
+
+        Ok(MidgardClient {
+            client,
+            config: config.clone(),
+            next_request: Mutex::new(Instant::now()),
+        })
+    }
+
+    // Datasets are fetched from the URLs in the `[datasets]` section of the config file.
+    pub async fn fetch_depth_history(&self, url: &str) -> Result<DepthHistoryResponse> {
+        self.fetch(url).await
+    }
+
+    pub async fn fetch_rune_pool_history(&self, url: &str) -> Result<RunePoolHistoryResponse> {
+        self.fetch(url).await
+    }
+
+    // The error names the URL, the number of attempts and the last failure, e.g. the status and
+    // the start of the body Midgard answered with.
+    async fn fetch<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut attempt = 0;
+
+        loop {
+            self.wait_for_rate_limit().await;
+
+            let (message, retry_after) = match self.attempt(url).await {
+                Ok(response) => return Ok(response),
+                Err(Failure::Fatal(message)) => (message, None),
+                Err(Failure::Retryable(message, retry_after))
+                    if attempt < self.config.max_retries =>
+                {
+                    (message, Some(self.retry_delay(attempt, retry_after)))
+                }
+                Err(Failure::Retryable(message, _)) => (message, None),
+            };
+
+            let Some(delay) = retry_after else {
+                return Err(Error::OperationFailed(format!(
+                    "Cannot fetch {} after {} attempt(s): {}",
+                    url,
+                    attempt + 1,
+                    message
+                )));
+            };
+
+            attempt += 1;
+            error(format!(
+                "Midgard request to {} failed: {}, retry {} of {} in {}ms",
+                url,
+                message,
+                attempt,
+                self.config.max_retries,
+                delay.as_millis()
+            ));
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn attempt<T: DeserializeOwned>(&self, url: &str) -> core::result::Result<T, Failure> {
+        let response = self.client.get(url).send().await.map_err(|e| {
+            // the request never got an answer, a later attempt may
+            if e.is_timeout() || e.is_connect() || e.is_request() {
+                Failure::Retryable(format!("request failed: {}", e), None)
+            } else {
+                Failure::Fatal(format!("request failed: {}", e))
+            }
+        })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            let body = response.text().await.unwrap_or_default();
+            let message = format!("HTTP {}: {}", status, truncate(&body, ERROR_BODY_LIMIT));
+
+            return Err(
+                if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                    Failure::Retryable(message, retry_after)
+                } else {
+                    Failure::Fatal(message)
+                },
+            );
+        }
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| Failure::Retryable(format!("reading the response failed: {}", e), None))?;
+
+        serde_json::from_str(&body).map_err(|e| {
+            Failure::Fatal(format!(
+                "unexpected response ({}): {}",
+                e,
+                truncate(&body, ERROR_BODY_LIMIT)
+            ))
+        })
+    }
+
+    // `1 / requests_per_second`, None without a limit. Capped at MAX_REQUEST_INTERVAL so a rate
+    // too small for a Duration cannot panic, validate already rejects such rates.
+    fn request_interval(&self) -> Option<Duration> {
+        if self.config.requests_per_second <= 0.0 {
+            return None;
+        }
+
+        let interval = Duration::try_from_secs_f64(1.0 / self.config.requests_per_second)
+            .unwrap_or(MAX_REQUEST_INTERVAL);
+
+        Some(interval.min(MAX_REQUEST_INTERVAL))
+    }
+
+    // Spaces requests `1 / requests_per_second` apart, across every caller of the client.
+    async fn wait_for_rate_limit(&self) {
+        let Some(interval) = self.request_interval() else {
+            return;
+        };
+
+        let slot = {
+            let mut next_request = self.next_request.lock().await;
+            let slot = (*next_request).max(Instant::now());
+            *next_request = slot + interval;
+            slot
+        };
+
+        sleep_until(slot).await;
+    }
+
+    // The server's Retry-After when it sent one, otherwise the backoff, both capped at
+    // max_backoff_ms so a server asking for hours cannot stall the run.
+    fn retry_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(retry_after) => retry_after.min(Duration::from_millis(self.config.max_backoff_ms)),
+            None => self.backoff(attempt),
+        }
+    }
+
+    // initial_backoff_ms doubled per retry and capped at max_backoff_ms, with up to half of it
+    // as jitter so concurrent callers do not retry in lockstep
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .config
+            .initial_backoff_ms
+            .saturating_mul(2u64.saturating_pow(attempt))
+            .min(self.config.max_backoff_ms);
+        let jitter = rand::thread_rng().gen_range(0..=backoff / 2);
+
+        Duration::from_millis(backoff - backoff / 2 + jitter)
+    }
+}
+
+fn truncate(body: &str, limit: usize) -> &str {
+    match body.char_indices().nth(limit) {
+        Some((index, _)) => &body[..index],
+        None => body,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(initial_backoff_ms: u64, max_backoff_ms: u64) -> MidgardClient {
+        MidgardClient::new(&MidgardConfig {
+            initial_backoff_ms,
+            max_backoff_ms,
+            ..MidgardConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_with_up_to_half_as_jitter() {
+        let client = client(100, 10_000);
+
+        for (attempt, full) in [(0, 100), (1, 200), (2, 400), (3, 800)] {
+            for _ in 0..20 {
+                let backoff = client.backoff(attempt);
+                assert!(backoff >= Duration::from_millis(full / 2));
+                assert!(backoff <= Duration::from_millis(full));
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped_without_overflowing() {
+        let client = client(100, 1_000);
+
+        for attempt in [4, 10, 63, 64, u32::MAX] {
+            let backoff = client.backoff(attempt);
+            assert!(backoff >= Duration::from_millis(500));
+            assert!(backoff <= Duration::from_millis(1_000));
+        }
+    }
+
+    #[test]
+    fn validate_rejects_inconsistent_settings() {
+        let mut errors = Vec::new();
+        MidgardConfig::default().validate(&mut errors);
+        assert!(errors.is_empty());
+
+        let config = MidgardConfig {
+            timeout_secs: 0,
+            initial_backoff_ms: 1_000,
+            max_backoff_ms: 10,
+            requests_per_second: -1.0,
+            ..MidgardConfig::default()
+        };
+        config.validate(&mut errors);
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_max_backoff() {
+        let client = client(100, 1_000);
+
+        assert_eq!(
+            client.retry_delay(0, Some(Duration::from_millis(300))),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            client.retry_delay(0, Some(Duration::from_secs(3600))),
+            Duration::from_millis(1_000)
+        );
+        assert!(client.retry_delay(0, None) <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn request_interval_follows_the_rate_and_is_capped() {
+        let with_rate = |requests_per_second| MidgardClient {
+            config: MidgardConfig {
+                requests_per_second,
+                ..MidgardConfig::default()
+            },
+            ..client(100, 1_000)
+        };
+
+        assert_eq!(with_rate(0.0).request_interval(), None);
+        assert_eq!(
+            with_rate(4.0).request_interval(),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            with_rate(1e-300).request_interval(),
+            Some(MAX_REQUEST_INTERVAL)
+        );
+        assert_eq!(
+            with_rate(f64::MIN_POSITIVE).request_interval(),
+            Some(MAX_REQUEST_INTERVAL)
+        );
+    }
+
+    #[test]
+    fn validate_rejects_rates_too_slow_to_space_requests() {
+        for requests_per_second in [1e-300, 0.0001] {
+            let mut errors = Vec::new();
+            MidgardConfig {
+                requests_per_second,
+                ..MidgardConfig::default()
+            }
+            .validate(&mut errors);
+            assert_eq!(errors.len(), 1);
+        }
+    }
+
+    #[test]
+    fn truncate_cuts_at_characters() {
+        assert_eq!(truncate("héllo", 2), "hé");
+        assert_eq!(truncate("hi", 10), "hi");
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{handlers::BackendResult, runs::RunStatus};
+
+// records written are reported every this many records, and once more at the end
+const RECORDS_WRITTEN_EVERY: usize = 100;
+
+// Progress of a background run, streamed from `/runs/{id}/events`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum RunEvent {
+    RunStarted,
+    BackendStarted {
+        backend: String,
+        operation: String,
+    },
+    RecordsWritten {
+        backend: String,
+        dataset: String,
+        written: usize,
+        total: usize,
+    },
+    BackendTimedOut {
+        backend: String,
+        operation: String,
+        timeout_secs: u64,
+    },
+    BackendFinished {
+        #[serde(flatten)]
+        result: BackendResult,
+    },
+    OperationFinished {
+        message: String,
+        duration_ms: u128,
+    },
+    Error {
+        message: String,
+    },
+    RunFinished {
+        status: RunStatus,
+        message: String,
+    },
+}
+
+impl RunEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunEvent::RunStarted => "run-started",
+            RunEvent::BackendStarted { .. } => "backend-started",
+            RunEvent::RecordsWritten { .. } => "records-written",
+            RunEvent::BackendTimedOut { .. } => "backend-timed-out",
+            RunEvent::BackendFinished { .. } => "backend-finished",
+            RunEvent::OperationFinished { .. } => "operation-finished",
+            RunEvent::Error { .. } => "error",
+            RunEvent::RunFinished { .. } => "run-finished",
+        }
+    }
+}
+
+// Every event of one run. Subscribers get the events sent so far followed by the live ones, so
+// a stream opened in the middle of a run still sees it from the start.
+#[derive(Debug)]
+pub struct RunEvents {
+    history: Mutex<Vec<RunEvent>>,
+    sender: broadcast::Sender<RunEvent>,
+}
+
+tokio::task_local! {
+    static RUN_EVENTS: Arc<RunEvents>;
+}
+
+impl Default for RunEvents {
+    fn default() -> Self {
+        RunEvents {
+            history: Mutex::new(Vec::new()),
+            sender: broadcast::channel(1024).0,
+        }
+    }
+}
+
+impl RunEvents {
+    pub fn send(&self, event: RunEvent) {
+        if let Ok(mut history) = self.history.lock() {
+            history.push(event.clone());
+            // no receivers is fine, nobody is watching the run
+            let _ = self.sender.send(event);
+        }
+    }
+
+    pub fn subscribe(&self) -> (Vec<RunEvent>, broadcast::Receiver<RunEvent>) {
+        match self.history.lock() {
+            Ok(history) => (history.clone(), self.sender.subscribe()),
+            Err(_) => (Vec::new(), self.sender.subscribe()),
+        }
+    }
+
+    // The result of every backend operation finished so far, in the order they finished.
+    pub fn results(&self) -> Vec<BackendResult> {
+        let Ok(history) = self.history.lock() else {
+            return Vec::new();
+        };
+
+        history
+            .iter()
+            .filter_map(|event| match event {
+                RunEvent::BackendFinished { result } => Some(result.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Runs `future` with every event it emits sent to these subscribers.
+    pub async fn scope<F: Future>(self: Arc<Self>, future: F) -> F::Output {
+        RUN_EVENTS.scope(self, future).await
+    }
+}
+
+// Outside of a run there is no one to tell, the event is dropped.
+pub fn emit(event: RunEvent) {
+    let _ = RUN_EVENTS.try_with(|events| events.send(event));
+}
+
+pub fn backend_started(backend: &str, operation: &str) {
+    emit(RunEvent::BackendStarted {
+        backend: backend.to_string(),
+        operation: operation.to_string(),
+    });
+}
+
+pub fn records_written(backend: &str, dataset: &str, written: usize, total: usize) {
+    if written.is_multiple_of(RECORDS_WRITTEN_EVERY) || written == total {
+        emit(RunEvent::RecordsWritten {
+            backend: backend.to_string(),
+            dataset: dataset.to_string(),
+            written,
+            total,
+        });
+    }
+}
+
+// Logs the error and streams it, for the errors that do not stop the run.
+pub fn error(message: String) {
+    eprintln!("{}", message);
+    emit(RunEvent::Error { message });
+}
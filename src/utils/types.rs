@@ -7,4 +7,7 @@ pub enum Error {
     DataBaseCreationFailed(String),
     DataBaseInsertionFailed(String),
     DataBaseReadFailed(String),
+    InvalidConfiguration(String),
+    OperationTimedOut(String),
+    VerificationFailed(String),
 }
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    models::{depth_history_model::DepthHistory, rune_pool_history_model::RunePoolHistory},
+    utils::{
+        types::{Error, Result},
+        write_metrics_into_file::record_metrics,
+    },
+};
+
+// Floats closer than this relative difference count as the same value that drifted through a
+// conversion, e.g. a decimal string or a driver rounding, further apart they are a mismatch.
+const DRIFT_TOLERANCE: f64 = 1e-9;
+// start times listed per kind of problem in the error, the counts cover all of them
+const LISTED_RECORDS: usize = 5;
+
+pub enum Field {
+    Integer(i64),
+    Float(f64),
+}
+
+// A record that can be compared field by field with the record it was written from.
+pub trait Record {
+    fn start_time(&self) -> i64;
+    fn fields(&self) -> Vec<(&'static str, Field)>;
+}
+
+impl Record for DepthHistory {
+    fn start_time(&self) -> i64 {
+        self.start_time
+    }
+
+    fn fields(&self) -> Vec<(&'static str, Field)> {
+        vec![
+            ("start_time", Field::Integer(self.start_time)),
+            ("end_time", Field::Integer(self.end_time)),
+            ("asset_depth", Field::Integer(self.asset_depth)),
+            ("rune_depth", Field::Integer(self.rune_depth)),
+            ("asset_price", Field::Float(self.asset_price)),
+            ("asset_price_usd", Field::Float(self.asset_price_usd)),
+            ("liquidity_units", Field::Integer(self.liquidity_units)),
+            ("members_count", Field::Integer(self.members_count)),
+            ("synth_units", Field::Integer(self.synth_units)),
+            ("synth_supply", Field::Integer(self.synth_supply)),
+            ("units", Field::Integer(self.units)),
+            ("luvi", Field::Float(self.luvi)),
+        ]
+    }
+}
+
+impl Record for RunePoolHistory {
+    fn start_time(&self) -> i64 {
+        self.start_time
+    }
+
+    fn fields(&self) -> Vec<(&'static str, Field)> {
+        vec![
+            ("start_time", Field::Integer(self.start_time)),
+            ("end_time", Field::Integer(self.end_time)),
+            ("count", Field::Integer(self.count)),
+            ("units", Field::Integer(self.units)),
+        ]
+    }
+}
+
+// Outcome of comparing the stored records with the source. Stored records outside the source
+// window are left alone, they come from earlier runs.
+#[derive(Default)]
+pub struct Verification {
+    expected: usize,
+    missing: Vec<i64>,
+    duplicated: Vec<i64>,
+    // start time and the fields that differ, e.g. `units: stored 11, expected 10`
+    mismatched: Vec<(i64, Vec<String>)>,
+    drifted: usize,
+    max_drift: f64,
+}
+
+fn relative_difference(stored: f64, expected: f64) -> f64 {
+    (stored - expected).abs() / stored.abs().max(expected.abs())
+}
+
+pub fn compare<T: Record>(source: &[T], stored: &[T]) -> Verification {
+    let mut by_start_time: HashMap<i64, Vec<&T>> = HashMap::new();
+    for record in stored {
+        by_start_time
+            .entry(record.start_time())
+            .or_default()
+            .push(record);
+    }
+
+    let mut verification = Verification {
+        expected: source.len(),
+        ..Verification::default()
+    };
+
+    for expected in source {
+        let start_time = expected.start_time();
+        let Some(copies) = by_start_time.get(&start_time) else {
+            verification.missing.push(start_time);
+            continue;
+        };
+
+        if copies.len() > 1 {
+            verification.duplicated.push(start_time);
+        }
+
+        // every copy has to match, a duplicate with other values is a mismatch as well
+        let mut differences = Vec::new();
+        let mut drifted = false;
+        for copy in copies {
+            for ((name, expected), (_, stored)) in expected.fields().into_iter().zip(copy.fields())
+            {
+                match (stored, expected) {
+                    (Field::Integer(stored), Field::Integer(expected)) if stored != expected => {
+                        differences.push(format!(
+                            "{}: stored {}, expected {}",
+                            name, stored, expected
+                        ));
+                    }
+                    (Field::Float(stored), Field::Float(expected))
+                        if stored != expected && !(stored.is_nan() && expected.is_nan()) =>
+                    {
+                        let drift = relative_difference(stored, expected);
+                        if drift <= DRIFT_TOLERANCE {
+                            drifted = true;
+                            verification.max_drift = verification.max_drift.max(drift);
+                        } else {
+                            differences.push(format!(
+                                "{}: stored {}, expected {}",
+                                name, stored, expected
+                            ));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if drifted {
+            verification.drifted += 1;
+        }
+        if !differences.is_empty() {
+            differences.dedup();
+            verification.mismatched.push((start_time, differences));
+        }
+    }
+
+    verification
+}
+
+fn listed<T>(items: &[T], describe: impl Fn(&T) -> String) -> String {
+    let mut listed: Vec<String> = items.iter().take(LISTED_RECORDS).map(describe).collect();
+    if items.len() > LISTED_RECORDS {
+        listed.push(format!("{} more", items.len() - LISTED_RECORDS));
+    }
+    listed.join(", ")
+}
+
+impl Verification {
+    fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.duplicated.is_empty() && self.mismatched.is_empty()
+    }
+
+    pub fn describe(&self, backend: &str, dataset: &str) -> String {
+        let mut line = format!(
+            "Verified {} in {} : {} records, {} missing, {} duplicated, {} mismatched, {} with float drift",
+            dataset,
+            backend,
+            self.expected,
+            self.missing.len(),
+            self.duplicated.len(),
+            self.mismatched.len(),
+            self.drifted
+        );
+        if self.drifted > 0 {
+            line.push_str(&format!(" (max relative difference {:e})", self.max_drift));
+        }
+        line.push('\n');
+        line
+    }
+
+    // Float drift alone is reported but does not fail the backend, the values are still equal
+    // within DRIFT_TOLERANCE.
+    pub fn into_result(self, backend: &str, dataset: &str) -> Result<()> {
+        if self.is_intact() {
+            return Ok(());
+        }
+
+        let mut problems = Vec::new();
+        if !self.missing.is_empty() {
+            problems.push(format!(
+                "{} missing ({})",
+                self.missing.len(),
+                listed(&self.missing, |start_time| start_time.to_string())
+            ));
+        }
+        if !self.duplicated.is_empty() {
+            problems.push(format!(
+                "{} duplicated ({})",
+                self.duplicated.len(),
+                listed(&self.duplicated, |start_time| start_time.to_string())
+            ));
+        }
+        if !self.mismatched.is_empty() {
+            problems.push(format!(
+                "{} mismatched ({})",
+                self.mismatched.len(),
+                listed(&self.mismatched, |(start_time, differences)| {
+                    format!("{}: {}", start_time, differences.join("; "))
+                })
+            ));
+        }
+
+        Err(Error::VerificationFailed(format!(
+            "{} in {} does not match the {} source records: {}",
+            dataset,
+            backend,
+            self.expected,
+            problems.join(", ")
+        )))
+    }
+}
+
+// Compares what `backend` returned with the source, records the result in the metrics and fails
+// when records are missing, duplicated or mismatched.
+pub fn verify<T: Record>(backend: &str, dataset: &str, source: &[T], stored: &[T]) -> Result<()> {
+    let verification = compare(source, stored);
+    record_metrics(verification.describe(backend, dataset));
+    verification.into_result(backend, dataset)
+}
+
+// Decodes the JSON values of a key-value store scan.
+pub fn decode_records<T: DeserializeOwned>(values: Vec<Vec<u8>>, backend: &str) -> Result<Vec<T>> {
+    values
+        .iter()
+        .map(|val| {
+            serde_json::from_slice(val).map_err(|e| {
+                Error::DataBaseReadFailed(format!(
+                    "Failed to deserialize a record from {}: {:?}",
+                    backend, e
+                ))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(start_time: i64, units: i64) -> RunePoolHistory {
+        RunePoolHistory {
+            start_time,
+            end_time: start_time + 3600,
+            count: 1,
+            units,
+        }
+    }
+
+    fn depth(start_time: i64, asset_price_usd: f64) -> DepthHistory {
+        DepthHistory {
+            start_time,
+            end_time: start_time + 3600,
+            asset_depth: 1,
+            rune_depth: 2,
+            asset_price: 0.5,
+            asset_price_usd,
+            liquidity_units: 3,
+            members_count: 4,
+            synth_units: 5,
+            synth_supply: 6,
+            units: 7,
+            luvi: 0.25,
+        }
+    }
+
+    #[test]
+    fn identical_records_are_intact() {
+        let source = vec![record(0, 10), record(3600, 11)];
+        let verification = compare(&source, &source);
+
+        assert!(verification.is_intact());
+        assert!(verification
+            .into_result("RocksDB", "rune pool history")
+            .is_ok());
+    }
+
+    #[test]
+    fn records_outside_the_source_are_ignored() {
+        let source = vec![record(0, 10)];
+        let stored = vec![record(0, 10), record(-3600, 1)];
+
+        assert!(compare(&source, &stored).is_intact());
+    }
+
+    #[test]
+    fn missing_duplicated_and_mismatched_records_are_reported() {
+        let source = vec![record(0, 10), record(3600, 11), record(7200, 12)];
+        let stored = vec![record(0, 10), record(0, 10), record(3600, 99)];
+        let verification = compare(&source, &stored);
+
+        assert_eq!(verification.missing, vec![7200]);
+        assert_eq!(verification.duplicated, vec![0]);
+        assert_eq!(
+            verification.mismatched,
+            vec![(3600, vec!["units: stored 99, expected 11".to_string()])]
+        );
+
+        let err = verification
+            .into_result("RocksDB", "rune pool history")
+            .unwrap_err();
+        assert!(matches!(err, Error::VerificationFailed(_)));
+    }
+
+    #[test]
+    fn a_duplicate_with_other_values_is_a_mismatch() {
+        let source = vec![record(0, 10)];
+        let stored = vec![record(0, 10), record(0, 20)];
+        let verification = compare(&source, &stored);
+
+        assert_eq!(verification.duplicated, vec![0]);
+        assert_eq!(verification.mismatched.len(), 1);
+    }
+
+    #[test]
+    fn float_drift_within_tolerance_passes() {
+        let source = vec![depth(0, 1.0)];
+        let stored = vec![depth(0, 1.0 + 1e-12)];
+        let verification = compare(&source, &stored);
+
+        assert_eq!(verification.drifted, 1);
+        assert!(verification.max_drift > 0.0);
+        assert!(verification
+            .into_result("Postgres", "depth history")
+            .is_ok());
+    }
+
+    #[test]
+    fn float_difference_beyond_tolerance_fails() {
+        let source = vec![depth(0, 1.0)];
+        let stored = vec![depth(0, 1.001)];
+        let verification = compare(&source, &stored);
+
+        assert_eq!(verification.drifted, 0);
+        assert_eq!(verification.mismatched.len(), 1);
+        assert!(verification
+            .into_result("Postgres", "depth history")
+            .is_err());
+    }
+
+    #[test]
+    fn long_lists_are_shortened() {
+        let items: Vec<i64> = (0..8).collect();
+
+        assert_eq!(
+            listed(&items, |item| item.to_string()),
+            "0, 1, 2, 3, 4, 3 more"
+        );
+    }
+}
@@ -1,15 +1,76 @@
-use std::{fs::OpenOptions, io::Write};
+use std::{
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    sync::{Arc, Mutex},
+};
 
-pub fn write_metrics_into_file(metrics_message: String) {
-    let file_path = "performance-metrics.txt";
+pub const METRICS_FILE: &str = "performance-metrics.txt";
+
+// Metrics lines recorded by one task, e.g. a background run or a CLI command printing JSON.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsSink {
+    lines: Arc<Mutex<Vec<String>>>,
+    // keeps the lines off stdout
+    quiet: bool,
+}
+
+tokio::task_local! {
+    static METRICS_SINK: MetricsSink;
+}
+
+impl MetricsSink {
+    pub fn quiet() -> Self {
+        MetricsSink {
+            quiet: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines
+            .lock()
+            .map(|lines| lines.clone())
+            .unwrap_or_default()
+    }
+
+    // Runs `future` and collects every metrics line it records, as it is recorded.
+    pub async fn collect<F: Future>(self, future: F) -> F::Output {
+        METRICS_SINK.scope(self, future).await
+    }
+}
 
+pub fn write_metrics_into_file(metrics_message: String) {
     let mut file = OpenOptions::new()
         .append(true)
         .create(true)
-        .open(file_path)
+        .open(METRICS_FILE)
         .expect("Failed to open the file for writing");
 
     if let Err(e) = file.write_all(metrics_message.as_bytes()) {
         eprintln!("Error writing to file: {}", e);
     }
 }
+
+// Prints a metrics line, and hands it to the sink of the current task if there is one.
+pub fn print_metrics(metrics_message: &str) {
+    let quiet = METRICS_SINK
+        .try_with(|sink| {
+            if let Ok(mut lines) = sink.lines.lock() {
+                lines.push(metrics_message.trim_end().to_string());
+            }
+            sink.quiet
+        })
+        .unwrap_or(false);
+
+    if !quiet {
+        println!("{}", metrics_message);
+    }
+}
+
+// Prints a metrics line and appends it to the metrics file.
+pub fn record_metrics(metrics_message: String) {
+    print_metrics(&metrics_message);
+
+    write_metrics_into_file(metrics_message);
+}